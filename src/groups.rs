@@ -0,0 +1,306 @@
+//! Track groups, which MiniDisc stores inside the disc title.
+//!
+//! A disc with groups has a title like `0;Disc Title//1-3;First Group//5;Second Group//`,
+//! where each entry after the disc title is a 1-based, inclusive range of
+//! tracks followed by the group's name. Titles without the `0;` header are
+//! plain titles, even if they happen to contain `//` or `;`.
+
+/// A range of consecutive tracks with a shared title
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackGroup {
+    pub title: String,
+    /// The first track in the group, 0-based
+    pub first: usize,
+    /// The last track in the group, 0-based and inclusive
+    pub last: usize,
+}
+
+impl TrackGroup {
+    pub fn contains(&self, track: usize) -> bool {
+        (self.first..=self.last).contains(&track)
+    }
+
+    pub fn overlaps(&self, first: usize, last: usize) -> bool {
+        self.first <= last && first <= self.last
+    }
+}
+
+/// A disc title split into the title itself and its groups
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupedTitle {
+    pub title: String,
+    pub groups: Vec<TrackGroup>,
+}
+
+impl GroupedTitle {
+    /// Split a raw disc title, as read from the device, into its parts.
+    pub fn parse(raw: &str) -> Self {
+        let Some((title, entries)) = raw.strip_prefix("0;").and_then(|r| r.split_once("//")) else {
+            return Self {
+                title: raw.to_string(),
+                groups: Vec::new(),
+            };
+        };
+
+        let mut parsed = Self {
+            title: title.to_string(),
+            groups: Vec::new(),
+        };
+        for entry in entries.split("//").filter(|e| !e.is_empty()) {
+            let Some((range, name)) = entry.split_once(';') else {
+                log::warn!("Ignoring malformed group entry {entry:?}");
+                continue;
+            };
+
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let (Ok(first), Ok(last)) = (first.parse::<usize>(), last.parse::<usize>()) else {
+                log::warn!("Ignoring malformed group entry {entry:?}");
+                continue;
+            };
+
+            if first == 0 || last < first {
+                log::warn!("Ignoring malformed group entry {entry:?}");
+                continue;
+            }
+
+            parsed.groups.push(TrackGroup {
+                title: name.to_string(),
+                first: first - 1,
+                last: last - 1,
+            });
+        }
+
+        parsed.groups.sort_by_key(|g| g.first);
+        parsed
+    }
+
+    /// Join the title and groups back together to be written to the device.
+    pub fn to_raw(&self) -> String {
+        if self.groups.is_empty() {
+            return self.title.clone();
+        }
+
+        let mut raw = format!("0;{}//", self.title);
+        for group in &self.groups {
+            if group.first == group.last {
+                raw.push_str(&format!("{};{}//", group.first + 1, group.title));
+            } else {
+                raw.push_str(&format!("{}-{};{}//", group.first + 1, group.last + 1, group.title));
+            }
+        }
+
+        raw
+    }
+
+    /// The index of the group a track belongs to, if any
+    pub fn group_of(&self, track: usize) -> Option<usize> {
+        self.groups.iter().position(|g| g.contains(track))
+    }
+
+    /// Cut groups down to the tracks the disc really has. Titles written by
+    /// other tools, or left over from before tracks were deleted, can name
+    /// tracks past the end.
+    pub fn clamp(&mut self, track_count: usize) {
+        self.groups.retain(|g| g.first < track_count);
+        for group in &mut self.groups {
+            group.last = group.last.min(track_count - 1);
+        }
+    }
+}
+
+/// Which group each track is in, as an index into `groups`.
+fn membership(groups: &[TrackGroup], track_count: usize) -> Vec<Option<usize>> {
+    (0..track_count)
+        .map(|track| groups.iter().position(|g| g.contains(track)))
+        .collect()
+}
+
+/// Rebuild the group ranges from which group each track is in, dropping any
+/// group left without tracks.
+fn from_membership(groups: &[TrackGroup], membership: &[Option<usize>]) -> Vec<TrackGroup> {
+    let mut rebuilt: Vec<TrackGroup> = groups.iter()
+        .enumerate()
+        .filter_map(|(i, group)| {
+            let first = membership.iter().position(|m| *m == Some(i))?;
+            let last = membership.iter().rposition(|m| *m == Some(i))?;
+            Some(TrackGroup { title: group.title.clone(), first, last })
+        })
+        .collect();
+
+    rebuilt.sort_by_key(|g| g.first);
+    rebuilt
+}
+
+/// Work out where a track has to move to so it joins the end of a group, or
+/// a new group of its own if `group` is `None`, along with the groups
+/// afterwards.
+pub fn move_to_group(
+    groups: &[TrackGroup],
+    track_count: usize,
+    track: usize,
+    group: Option<usize>,
+    new_title: &str,
+) -> (usize, Vec<TrackGroup>) {
+    let mut groups = groups.to_vec();
+    if track >= track_count {
+        return (track, groups);
+    }
+
+    let mut membership = membership(&groups, track_count);
+    let current = membership[track];
+
+    let (to, target) = match group {
+        Some(g) if current == Some(g) => return (track, groups),
+        // Removing the track first shifts a later group back by one
+        Some(g) if track < groups[g].first => (groups[g].last, g),
+        Some(g) => (groups[g].last + 1, g),
+        None => {
+            groups.push(TrackGroup { title: new_title.to_string(), first: track, last: track });
+
+            // Step out past the end of the current group, so it stays in one piece
+            let to = match current {
+                Some(c) => groups[c].last,
+                None => track,
+            };
+            (to, groups.len() - 1)
+        }
+    };
+
+    // A group running past the last track would put it out of range
+    membership.remove(track);
+    let to = to.min(membership.len());
+    membership.insert(to, Some(target));
+
+    (to, from_membership(&groups, &membership))
+}
+
+/// The groups after moving a track from `from` to `to`. Every other track
+/// stays in its group, and the moved track joins the group it lands inside,
+/// keeps its own if it's still next to it, or is left ungrouped.
+pub fn move_track(groups: &[TrackGroup], track_count: usize, from: usize, to: usize) -> Vec<TrackGroup> {
+    if from >= track_count || to >= track_count {
+        return groups.to_vec();
+    }
+
+    let mut membership = membership(groups, track_count);
+    let moving = membership.remove(from);
+
+    let above = to.checked_sub(1).and_then(|i| membership.get(i)).copied();
+    let below = membership.get(to).copied();
+    let landed_in = if above == below && above.is_some_and(|m| m.is_some()) {
+        above.flatten()
+    } else if moving.is_some()
+        && (above == Some(moving) || below == Some(moving) || !membership.contains(&moving))
+    {
+        moving
+    } else {
+        None
+    };
+
+    membership.insert(to, landed_in);
+    from_membership(groups, &membership)
+}
+
+/// The groups after erasing `tracks`, shrinking each group by the tracks it
+/// loses and dropping any left empty.
+pub fn remove_tracks(groups: &[TrackGroup], track_count: usize, tracks: &[usize]) -> Vec<TrackGroup> {
+    let membership: Vec<Option<usize>> = membership(groups, track_count)
+        .into_iter()
+        .enumerate()
+        .filter(|(track, _)| !tracks.contains(track))
+        .map(|(_, m)| m)
+        .collect();
+
+    from_membership(groups, &membership)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_trims_groups_past_the_end() {
+        let mut title = GroupedTitle::parse("0;Disc//1-2;First//3-9;Second//12;Gone//");
+        title.clamp(4);
+
+        assert_eq!(title.groups, vec![
+            TrackGroup { title: "First".to_string(), first: 0, last: 1 },
+            TrackGroup { title: "Second".to_string(), first: 2, last: 3 },
+        ]);
+    }
+
+    #[test]
+    fn move_to_group_past_the_end() {
+        // The second group claims tracks 3-10 of a 4 track disc
+        let groups = GroupedTitle::parse("0;Disc//1-2;First//3-10;Second//").groups;
+
+        let (to, groups) = move_to_group(&groups, 4, 0, Some(1), "New");
+        assert_eq!(to, 3);
+        assert_eq!(groups, vec![
+            TrackGroup { title: "First".to_string(), first: 0, last: 0 },
+            TrackGroup { title: "Second".to_string(), first: 1, last: 3 },
+        ]);
+    }
+
+    #[test]
+    fn move_to_group_out_of_range_track() {
+        let groups = GroupedTitle::parse("0;Disc//1-2;First//").groups;
+        assert_eq!(move_to_group(&groups, 2, 5, Some(0), "New"), (5, groups));
+    }
+
+    #[test]
+    fn move_track_keeps_other_tracks_in_their_groups() {
+        // Tracks 1-2 and 3-4 are grouped, 5 isn't
+        let groups = GroupedTitle::parse("0;Disc//1-2;First//3-4;Second//").groups;
+
+        // Out of the first group and past the second
+        assert_eq!(move_track(&groups, 5, 0, 4), vec![
+            TrackGroup { title: "First".to_string(), first: 0, last: 0 },
+            TrackGroup { title: "Second".to_string(), first: 1, last: 2 },
+        ]);
+
+        // Into the middle of the second group
+        assert_eq!(move_track(&groups, 5, 4, 3), vec![
+            TrackGroup { title: "First".to_string(), first: 0, last: 1 },
+            TrackGroup { title: "Second".to_string(), first: 2, last: 4 },
+        ]);
+
+        // A group's only track takes the group with it
+        let groups = GroupedTitle::parse("0;Disc//1;Only//").groups;
+        assert_eq!(move_track(&groups, 3, 0, 2), vec![
+            TrackGroup { title: "Only".to_string(), first: 2, last: 2 },
+        ]);
+    }
+
+    #[test]
+    fn remove_tracks_shrinks_groups() {
+        // Tracks 1-2 and 3-4 are grouped, 5 isn't
+        let groups = GroupedTitle::parse("0;Disc//1-2;First//3-4;Second//").groups;
+
+        assert_eq!(remove_tracks(&groups, 5, &[0, 2, 3]), vec![
+            TrackGroup { title: "First".to_string(), first: 0, last: 0 },
+        ]);
+        assert_eq!(remove_tracks(&groups, 5, &[4]), groups);
+    }
+
+    #[test]
+    fn plain_titles_are_kept_whole() {
+        for raw in ["Greatest Hits", "http://example.com", "AC//DC Live", "Best Of; Vol 2//Bonus", "0;Nothing Else"] {
+            let parsed = GroupedTitle::parse(raw);
+            assert_eq!(parsed, GroupedTitle { title: raw.to_string(), groups: Vec::new() });
+            assert_eq!(parsed.to_raw(), raw);
+        }
+    }
+
+    #[test]
+    fn grouped_titles_round_trip() {
+        for raw in ["0;Disc//1-2;First//3;Second//", "0;Best Of; Vol 2//1-4;Side A; Live//"] {
+            assert_eq!(GroupedTitle::parse(raw).to_raw(), raw);
+        }
+
+        assert_eq!(GroupedTitle::parse("0;Best Of; Vol 2//1-4;Side A; Live//"), GroupedTitle {
+            title: "Best Of; Vol 2".to_string(),
+            groups: vec![TrackGroup { title: "Side A; Live".to_string(), first: 0, last: 3 }],
+        });
+    }
+}
@@ -1,19 +1,37 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::{path::PathBuf, sync::{mpsc, Arc, RwLock}, thread::sleep, time::{Duration, Instant}};
+mod groups;
+mod rename;
+mod settings;
+mod titles;
 
-use anyhow::Result;
+use std::{collections::{HashMap, HashSet, VecDeque}, hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState}, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, Arc, LazyLock, RwLock}, time::{Duration, Instant}};
+
+use anyhow::{anyhow, Result};
 use eframe::egui::{self, include_image, scroll_area::ScrollBarVisibility, Align, FontData, FontDefinitions, FontFamily, ProgressBar};
+use cross_usb::{prelude::*, DeviceInfo};
 use egui_extras::{install_image_loaders, Column, TableBuilder};
 use futures_lite::future;
-use minidisc::netmd::{commands::{DeviceStatus, Disc, OperatingStatus as OS}, interface::{Action, Direction, MDTrack}, NetMDContext, DEVICE_IDS_CROSSUSB};
+use groups::{move_to_group, move_track, remove_tracks, GroupedTitle, TrackGroup};
+use rename::{FindReplace, Numbering, TrackTitles};
+use settings::{DeviceSettings, DurationFormat, Settings, TableDensity, CHUNK_SIZES, DEFAULT_CHUNK_SIZE, SETTINGS_KEY, UPLOAD_QUEUE_KEY};
+use titles::{fit_in_toc, half_width_title, sanitize_title, title_change_hint};
+use minidisc::netmd::{commands::{DeviceStatus, Disc, OperatingStatus as OS, Track}, interface::{Action, Direction, MDTrack, WireFormat}, NetMDContext, DEVICE_IDS_CROSSUSB};
+
+const DEFAULT_WINDOW_SIZE: [f32; 2] = [1024.0, 768.0];
+const DEFAULT_PIXELS_PER_POINT: f32 = 1.5;
+const PIXELS_PER_POINT_RANGE: std::ops::RangeInclusive<f32> = 0.75..=3.0;
+
+/// When the app started, which command log timestamps count from
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
 
 fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    LazyLock::force(&STARTED);
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1024.0, 768.0]),
+            .with_inner_size(DEFAULT_WINDOW_SIZE),
         ..Default::default()
     };
 
@@ -38,11 +56,10 @@ fn main() -> eframe::Result {
         "Rust Minidisc Application",
         options,
         Box::new(|cc| {
-            cc.egui_ctx.set_pixels_per_point(1.5);
             cc.egui_ctx.set_fonts(fonts);
 
             install_image_loaders(&cc.egui_ctx);
-            Ok(Box::<MinidiscManager>::default())
+            Ok(Box::new(MinidiscManager::new(cc)))
         }),
     )
 }
@@ -50,13 +67,100 @@ fn main() -> eframe::Result {
 #[derive(Default)]
 struct MinidiscManager {
     md_state: Arc<RwLock<PlayerState>>,
-    md_channel: Option<mpsc::Sender<PlayerCommand>>,
+    md_channel: Option<async_channel::Sender<PlayerCommand>>,
+    /// The thread talking to the device, kept so the next one can wait for
+    /// it to let go of the device
+    worker: Option<std::thread::JoinHandle<()>>,
 
     track_listing_table: TrackListingTable,
+
+    settings: Settings,
+    show_settings: bool,
+    show_shortcuts: bool,
+    show_about: bool,
+    show_device_info: bool,
+    /// Skips which haven't been sent yet, and when the last one was added
+    pending_skip: Option<(isize, Instant)>,
+    /// The start of an A-B loop whose end hasn't been picked yet
+    loop_start: Option<(u8, Duration)>,
+
+    /// The track details being edited before an upload starts
+    upload_dialog: Option<UploadDialog>,
+    /// Files waiting for the user to start uploading them, in order
+    upload_queue: Option<Vec<QueuedUpload>>,
+    /// Files named in an imported playlist which don't exist
+    playlist_missing: Vec<PathBuf>,
+    /// The disc title currently being edited
+    disc_title_edit: Option<String>,
+    /// A track deletion waiting for the user to confirm it
+    delete_confirmation: Option<DeleteConfirmation>,
+    /// The find and replace across every track title, while it's open
+    find_replace: Option<FindReplace>,
+    /// The tracks being numbered and how, while the numbering window is open
+    numbering: Option<(Vec<usize>, Numbering)>,
+    /// Show elapsed time across the whole disc instead of the current track
+    show_disc_time: bool,
+    /// The attached devices to choose between, with their display names
+    device_picker: Option<Vec<(String, DeviceInfo)>>,
+    /// A copy of the device settings shared with the worker thread
+    device_settings: Arc<RwLock<DeviceSettings>>,
+    /// The contents of discs read this session, shared with the worker thread
+    disc_cache: DiscCache,
+    show_error_log: bool,
+    /// Whether to show the log of commands run on the device
+    show_command_log: bool,
+    /// What the user has typed to confirm erasing the disc, while asking
+    erase_confirmation: Option<String>,
+    /// How far along closing the window during a transfer is
+    quit: Option<QuitState>,
+    /// Whether to show every track on the disc as one timeline
+    show_disc_timeline: bool,
+    /// Held while uploading to stop the computer from sleeping, or the
+    /// reason that isn't possible
+    keep_awake: Option<Result<keepawake::KeepAwake, String>>,
+}
+
+struct DeleteConfirmation {
+    tracks: Vec<usize>,
+    dont_ask_again: bool,
+}
+
+/// The steps of closing the window while a track is being uploaded
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum QuitState {
+    /// Asking the user whether to stop the transfer
+    Asking,
+    /// Waiting for the transfer to stop, then closing
+    Cancelling,
+    /// Closing without waiting any longer
+    Quitting,
 }
 
 impl eframe::App for MinidiscManager {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings);
+
+        let queue: Vec<SavedUpload> = self.upload_queue.iter().flatten().map(SavedUpload::from).collect();
+        eframe::set_value(storage, UPLOAD_QUEUE_KEY, &queue);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.settings.pixels_per_point = ctx.pixels_per_point();
+
+        // The worker exits by itself when connecting fails or the device is
+        // lost for good, which leaves nothing listening on the channel
+        if self.worker.as_ref().is_some_and(|w| w.is_finished()) {
+            self.md_channel = None;
+        }
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| self.file_menu(ui));
+                ui.menu_button("Device", |ui| self.device_menu(ui));
+                ui.menu_button("Help", |ui| self.help_menu(ui));
+            });
+        });
+
         egui::TopBottomPanel::top("status_bar").exact_height(35.).show(ctx, |ui| {
             ui.columns_const(|[col_1, col_2]| {
                 col_1.horizontal_centered(|ui| {
@@ -64,379 +168,4720 @@ impl eframe::App for MinidiscManager {
                     ui.heading("Minidisc Manager");
                 });
                 col_2.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
-                    if !self.md_state.read().unwrap().connected && ui.button("Connect").clicked() {
-                        self.connect_to_device();
-                    } else if let Some(state) = &self.md_state.read().unwrap().device_state {
-                        let state = match state.state.unwrap_or(OS::NoDisc) {
-                            OS::Ready => "✅",
-                            OS::Playing => "▶️",
-                            OS::Paused => "⏸️",
-                            OS::FastForward => "⏩",
-                            OS::Rewind => "⏪",
-                            OS::ReadingTOC => "🔄",
-                            OS::NoDisc => "No Disc",
-                            OS::DiscBlank => "Disc Blank",
-                            OS::ReadyForTransfer => "Ready",
-                        }.to_string();
-
-                        ui.label(state).on_hover_text("Status");
+                    ui.toggle_value(&mut self.show_settings, "⚙").on_hover_text("Settings");
+                    ui.toggle_value(&mut self.show_error_log, "📋").on_hover_text("Error log");
+
+                    let (connected, connecting, last_error, reconnect_attempt) = {
+                        let state = self.md_state.read().unwrap();
+                        (state.connected, state.connecting, state.last_error.clone(), state.reconnect_attempt)
+                    };
+
+                    if let Some(attempt) = reconnect_attempt {
+                        if ui.button("Stop").on_hover_text("Stop trying to reconnect").clicked() {
+                            self.disconnect();
+                        }
+                        ui.label(format!("Reconnecting… attempt {attempt}"));
+                        ui.spinner();
+                    } else if connecting {
+                        ui.label("Connecting…");
+                        ui.spinner();
+                    } else if !connected {
+                        let mut button = ui.button("Connect");
+                        if let Some(error) = last_error {
+                            button = button.on_hover_text(error);
+                        }
+
+                        if button.clicked() {
+                            self.choose_device();
+                        }
+                    } else {
+                        if ui.button("Disconnect").clicked() {
+                            self.disconnect();
+                        }
+                        if ui.button("🔄").on_hover_text("Read the disc again").clicked() {
+                            self.send_command(PlayerCommand::Refresh);
+                        }
+
+                        let (device_state, device_name, since_status, busy) = {
+                            let state = self.md_state.read().unwrap();
+                            // Polling waits while these are going on
+                            let busy = state.reading || state.preparing_transfer
+                                || state.progress.is_some() || state.queue_position.is_some();
+                            (state.device_state, state.device_name.clone(), state.last_status.map(|t| t.elapsed()), busy)
+                        };
+
+                        if let Some(state) = device_state {
+                            let (label, hint) = describe_status(state.state);
+                            let stale_after = STALE_STATUS_AFTER.max(self.settings.device.poll_interval * 3);
+
+                            match since_status {
+                                Some(since) if since > stale_after && !busy => {
+                                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {label}"))
+                                        .on_hover_text(format!("The device hasn't answered for {} seconds", since.as_secs()));
+                                }
+                                Some(since) => {
+                                    ui.label(label).on_hover_text(format!("{hint}\nUpdated {:.1} seconds ago", since.as_secs_f32()));
+                                }
+                                None => {
+                                    ui.label(label).on_hover_text(hint);
+                                }
+                            }
+                        }
+
+                        if let Some(name) = device_name {
+                            ui.label(name).on_hover_text("Connected device");
+                        }
+
+                        if self.write_protected() {
+                            ui.label("🔒").on_hover_text(WRITE_PROTECTED_HINT);
+                        }
+
+                        if self.md_state.read().unwrap().cached_contents {
+                            ui.spinner().on_hover_text("Showing this disc as it was last read, while checking it hasn't changed");
+                        }
                     }
 
                     ui.separator();
 
-                    if let Some(dc) = &self.md_state.read().unwrap().disc_contents {
-                        ui.add(egui::Label::new(dc.title()).truncate());
-                    }
+                    self.disc_title(ui);
                 });
             });
         });
 
+        let last_error = self.md_state.read().unwrap().last_error.clone();
+        if let Some(error) = last_error {
+            egui::TopBottomPanel::top("error_banner").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                        self.md_state.write().unwrap().last_error = None;
+                    }
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                });
+            });
+        }
+
         egui::TopBottomPanel::bottom("controls").exact_height(40.).show(ctx, |ui| {
             ui.columns_const(|[col_1, col_2, col_3]| {
                 col_1.horizontal_centered(|ui| {
-                    if ui.button("Upload").clicked() {
-                        if let Some(c) = self.md_channel.as_mut() {
-                            let _ = c.send(PlayerCommand::Upload("./bad_apple.raw".into()));
-                        }
+                    let shortcut_hint = |name: &str, shortcut: &egui::KeyboardShortcut| {
+                        format!("{name} ({})", ctx.format_shortcut(shortcut))
+                    };
+
+                    let upload_blocked = self.upload_blocked();
+                    if ui.add_enabled(upload_blocked.is_none(), egui::Button::new("Upload"))
+                        .on_hover_text(shortcut_hint("Upload tracks", &UPLOAD_SHORTCUT))
+                        .on_disabled_hover_text(upload_blocked.unwrap_or_default())
+                        .clicked()
+                        && self.md_channel.is_some()
+                    {
+                        let paths = self.pick_upload_files();
+                        self.start_upload(paths);
                     }
 
-                    if ui.button("⏯").clicked() {
-                        if let Some(c) = self.md_channel.as_mut() {
-                            let dev_state = self.md_state.read().unwrap().device_state;
-                            if dev_state.is_some_and(|s| s.state.is_some_and(|s| s == OS::Playing)) {
-                                let _ = c.send(PlayerCommand::Playback(Action::Pause));
-                            } else {
-                                let _ = c.send(PlayerCommand::Playback(Action::Play));
+                    let undo = self.md_state.read().unwrap().undo.as_ref().map(|u| u.description.clone());
+                    let button = ui.add_enabled(undo.is_some(), egui::Button::new("↶"));
+                    if let Some(description) = undo
+                        && button.on_hover_text(shortcut_hint(&format!("Undo {description}"), &UNDO_SHORTCUT)).clicked()
+                    {
+                        self.undo();
+                    }
+
+                    egui::ComboBox::from_id_salt("upload_format")
+                        .width(60.)
+                        .selected_text(self.settings.upload_format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in UploadFormat::ALL {
+                                ui.selectable_value(&mut self.settings.upload_format, format, format.to_string())
+                                    .on_hover_text(format.description());
                             }
-                        }
+                        })
+                        .response
+                        .on_hover_text(self.settings.upload_format.description());
+
+                    ui.separator();
+
+                    if ui.button("⏯").on_hover_text(shortcut_hint("Play/Pause", &PLAY_PAUSE_SHORTCUT)).clicked() {
+                        self.play_pause();
                     }
 
-                    if ui.button("⏹").clicked() {
-                        if let Some(c) = self.md_channel.as_mut() {
-                            let _ = c.send(PlayerCommand::Stop);
-                        }
+                    if ui.button("⏹").on_hover_text(shortcut_hint("Stop", &STOP_SHORTCUT)).clicked() {
+                        self.send_command(PlayerCommand::Stop);
                     }
 
-                    if ui.button("⏮").clicked() {
-                        if let Some(c) = self.md_channel.as_mut() {
-                            let _ = c.send(PlayerCommand::SkipTrack(Direction::Previous));
-                        }
+                    if ui.button("⏮").on_hover_text(shortcut_hint("Previous track", &PREVIOUS_SHORTCUT)).clicked() {
+                        self.queue_skip(-1);
                     }
 
-                    if ui.button("⏭").clicked() {
-                        if let Some(c) = self.md_channel.as_mut() {
-                            let _ = c.send(PlayerCommand::SkipTrack(Direction::Next));
+                    if ui.button("⏭").on_hover_text(shortcut_hint("Next track", &NEXT_SHORTCUT)).clicked() {
+                        self.queue_skip(1);
+                    }
+
+                    if self.md_state.read().unwrap().can_eject
+                        && ui.button("⏏").on_hover_text("Eject").clicked()
+                    {
+                        self.send_command(PlayerCommand::Eject);
+                    }
+
+                    ui.separator();
+
+                    let (repeat_mode, track_count) = {
+                        let state = self.md_state.read().unwrap();
+                        (state.repeat_mode, state.disc_contents.as_ref().map_or(0, |dc| dc.track_count()))
+                    };
+                    for (mode, icon, hint) in [
+                        (RepeatMode::Track, "🔂", "Repeat track"),
+                        (RepeatMode::Disc, "🔁", "Repeat disc"),
+                        (RepeatMode::Shuffle, "🔀", "Shuffle"),
+                    ] {
+                        let supported = mode != RepeatMode::Shuffle || track_count > 1;
+                        if ui.add_enabled(supported, egui::SelectableLabel::new(repeat_mode == mode, icon))
+                            .on_hover_text(hint)
+                            .on_disabled_hover_text("Shuffling needs a disc with more than one track")
+                            .clicked()
+                            && let Some(c) = self.md_channel.as_mut()
+                        {
+                            let new_mode = if repeat_mode == mode { RepeatMode::None } else { mode };
+                            let _ = c.send_blocking(PlayerCommand::SetRepeatMode(new_mode));
                         }
                     }
+
+                    ui.separator();
+                    self.ab_loop_controls(ui);
+
+                    let mut stop_after_track = self.md_state.read().unwrap().stop_after_track;
+                    if ui.checkbox(&mut stop_after_track, "Stop after")
+                        .on_hover_text("Stop when the current track finishes")
+                        .changed()
+                    {
+                        self.send_command(PlayerCommand::SetStopAfterTrack(stop_after_track));
+                    }
                 });
                 col_2.with_layout(egui::Layout::centered_and_justified(egui::Direction::TopDown), |ui| {
-                    if let Some(s) = self.md_state.read().unwrap().device_state
-                        && let Some(dc) = &self.md_state.read().unwrap().disc_contents
-                    {
-                        if (s.track as usize) < dc.tracks().len() {
-                            ui.add(ProgressBar::new(
-                                Duration::from(s.time).as_secs_f32() / dc.tracks()[s.track as usize].duration().as_duration().as_secs_f32()
-                            ).corner_radius(2.));
+                    let position = self.md_state.read().unwrap().current_track()
+                        .map(|(_, length, elapsed)| (elapsed, length));
+
+                    if let Some((elapsed, duration)) = position {
+                        let response = ui.add(ProgressBar::new(
+                            elapsed.as_secs_f32() / duration.as_secs_f32()
+                        ).corner_radius(2.))
+                            .interact(egui::Sense::click())
+                            .on_hover_cursor(egui::CursorIcon::PointingHand);
+
+                        // Only clicks count, so the start of a drag doesn't seek
+                        if response.clicked()
+                            && let Some(pos) = response.interact_pointer_pos()
+                            && let Some(c) = self.md_channel.as_mut()
+                        {
+                            let fraction = ((pos.x - response.rect.left()) / response.rect.width()).clamp(0.0, 1.0);
+                            let _ = c.send_blocking(PlayerCommand::Seek(duration.mul_f32(fraction)));
                         }
                     } else {
                         ui.add(ProgressBar::new(0.0).corner_radius(2.));
                     }
                 });
                 col_3.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
-                    if let Some(s) = self.md_state.read().unwrap().device_state {
-                        ui.label(pretty_duration(s.time.into()))
+                    ui.toggle_value(&mut self.show_disc_timeline, "🎞").on_hover_text("Disc timeline");
+
+                    let (elapsed, total) = self.playback_times();
+                    let text = format!("{} / {}", pretty_duration(elapsed, self.settings.duration_format), pretty_duration(total, self.settings.duration_format));
+
+                    let hint = if self.show_disc_time {
+                        "Disc time, click to show track time"
                     } else {
-                        ui.label("00:00:00")
+                        "Track time, click to show disc time"
+                    };
+
+                    if ui.add(egui::Label::new(text).sense(egui::Sense::click())).on_hover_text(hint).clicked() {
+                        self.show_disc_time = !self.show_disc_time;
+                    }
+
+                    if let Some(remaining) = self.disc_remaining() {
+                        ui.separator();
+                        ui.weak(format!("-{}", pretty_duration(remaining, self.settings.duration_format)))
+                            .on_hover_text("Time left until the end of the disc");
                     }
                 });
             });
         });
 
+        if self.show_disc_timeline {
+            egui::TopBottomPanel::bottom("disc_timeline").show(ctx, |ui| {
+                let state = self.md_state.read().unwrap();
+                let Some(dc) = &state.disc_contents else {
+                    ui.add(ProgressBar::new(0.0).corner_radius(2.));
+                    return;
+                };
+
+                let position = state.current_track().map(|(index, _, elapsed)| (index, elapsed));
+                if let Some(track) = disc_timeline(ui, dc, position)
+                    && let Some(c) = self.md_channel.as_ref()
+                {
+                    let _ = c.send_blocking(PlayerCommand::GoToTrack(track));
+                }
+            });
+        }
+
+        let write_protected = self.write_protected();
+        if let Some(dc) = &self.md_state.read().unwrap().disc_contents {
+            egui::TopBottomPanel::bottom("disc_summary").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let recorded: Duration = dc.tracks().iter().map(|t| t.duration().as_duration()).sum();
+                    let free = disc_free_time(dc);
+
+                    ui.label(format!("{} tracks", dc.track_count()));
+                    ui.separator();
+                    ui.label(format!("{} recorded", pretty_duration(recorded, self.settings.duration_format)));
+                    ui.separator();
+                    ui.label(format!("{} free", pretty_duration(free, self.settings.duration_format)))
+                        .on_hover_text("Remaining space when recording in SP");
+
+                    if self.settings.upload_format != UploadFormat::SP {
+                        ui.label(format!(
+                            "({} in {})",
+                            pretty_duration(free * self.settings.upload_format.time_multiplier(), self.settings.duration_format),
+                            self.settings.upload_format,
+                        ));
+                    }
+
+                    ui.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
+                        if ui.add_enabled(!write_protected, egui::Button::new("Erase Disc…").small())
+                            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                            .clicked()
+                        {
+                            self.erase_confirmation = Some(String::new());
+                        }
+                    });
+                });
+
+                capacity_bar(ui, dc);
+            });
+        }
+
+        if self.show_command_log {
+            self.command_log(ctx);
+        }
+
+        if self.show_error_log {
+            egui::TopBottomPanel::bottom("error_log").resizable(true).show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("Error Log");
+                    if ui.small_button("Clear").clicked() {
+                        self.md_state.write().unwrap().error_log.clear();
+                    }
+                });
+
+                egui::ScrollArea::vertical().stick_to_bottom(true).auto_shrink(false).show(ui, |ui| {
+                    for error in &self.md_state.read().unwrap().error_log {
+                        ui.label(error);
+                    }
+                });
+            });
+        }
+
+        self.keyboard_shortcuts(ctx);
+        self.send_pending_skip();
+        self.update_keep_awake();
+        self.settings_window(ctx);
+        self.help_windows(ctx);
+        self.device_info(ctx);
+        self.device_picker(ctx);
+        self.dropped_files(ctx);
+        self.upload_dialog(ctx);
+        self.upload_queue(ctx);
+        self.upload_errors(ctx);
+        self.playlist_missing(ctx);
+        self.find_replace_window(ctx);
+        self.numbering_window(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let state = self.md_state.read().unwrap();
-            if let Some(p) = state.progress {
-                ui.centered_and_justified(|ui| {
+            if state.preparing_transfer {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Waiting for the device to be ready…");
+                });
+            } else if let Some(p) = state.progress {
+                ui.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
+                    if ui.button("Cancel").clicked() {
+                        if let Some(c) = self.md_channel.as_mut() {
+                            let _ = c.send_blocking(PlayerCommand::CancelUpload);
+                        }
+                    }
+                    if let Some((path, title)) = &state.current_upload {
+                        let text = match state.queue_position {
+                            Some((current, total)) => format!("Uploading '{title}' ({current}/{total})"),
+                            None => format!("Uploading '{title}'"),
+                        };
+                        ui.label(text).on_hover_text(path.display().to_string());
+                    }
+                    if let Some(speed) = state.transfer_speed {
+                        ui.label(format!("{:.1}x realtime, ~{} remaining", speed.realtime, pretty_duration(speed.remaining, self.settings.duration_format)));
+                    }
                     ui.add(egui::ProgressBar::new(p).show_percentage().animate(true))
                 });
-            } else if state.reading || state.device_state.is_some_and(|s| s.state.is_some_and(|s| s == OS::ReadingTOC)) {
+            } else if (state.reading && state.busy_tracks.is_empty() && !state.cached_contents) || state.device_state.is_some_and(|s| s.state.is_some_and(|s| s == OS::ReadingTOC)) {
                 ui.centered_and_justified(|ui| {
                     ui.spinner()
                 });
+            } else if let Some(error) = &state.toc_error {
+                let can_eject = state.can_eject;
+                ui.vertical_centered(|ui| {
+                    ui.add_space(ui.available_height() / 3.0);
+                    ui.heading("Couldn't read disc contents").on_hover_text(error);
+                    ui.label("The disc's table of contents may be damaged");
+                    ui.horizontal(|ui| {
+                        if ui.button("Retry").clicked() {
+                            self.send_command(PlayerCommand::Refresh);
+                        }
+                        if can_eject && ui.button("Eject").clicked() {
+                            self.send_command(PlayerCommand::Eject);
+                        }
+                    });
+                });
             } else if state.device_state.is_some_and(|s| !s.disc_present) {
+                let (label, hint) = describe_status(Some(OS::NoDisc));
                 ui.centered_and_justified(|ui| {
-                    ui.heading("No Disc");
+                    ui.heading(label).on_hover_text(hint);
                 });
             } else if state.disc_contents.as_ref().is_some_and(|c| c.track_count() == 0) {
+                let (label, hint) = describe_status(Some(OS::DiscBlank));
                 ui.centered_and_justified(|ui| {
-                    ui.heading("Disk Blank");
+                    ui.heading(label).on_hover_text(hint);
                 });
             } else if let Some(c) = &state.disc_contents {
-                let playing_track = if let Some(s) = state.device_state {
-                    if s.state.is_some_and(|s| {
-                        s == OS::Playing || s == OS::Paused || s == OS::FastForward || s == OS::Rewind
-                    }) {
-                        Some(s.track as usize)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
+                let playing_track = state.playing_track();
+                let next_track = state.next_track();
 
-                self.track_listing_table.table(ui, c, playing_track, &mut self.md_channel);
+                self.track_listing_table.read_only = state.write_protected;
+                self.track_listing_table.duration_format = self.settings.duration_format;
+                self.track_listing_table.density = self.settings.table_density;
+                self.track_listing_table.busy.clone_from(&state.busy_tracks);
+                self.track_listing_table.table(ui, c, &state.groups, playing_track, next_track, &mut self.md_channel);
             }
         });
 
+        self.delete_confirmation(ctx);
+        self.erase_confirmation(ctx);
+        self.quit_confirmation(ctx);
+
         ctx.request_repaint();
     }
 }
 
 impl MinidiscManager {
-    fn connect_to_device(&mut self) {
-        let state = Arc::new(RwLock::new(PlayerState::default()));
-        let (send, recv) = mpsc::channel();
+    /// Create the app, restoring any settings saved by a previous launch.
+    fn new(cc: &eframe::CreationContext) -> Self {
+        let mut settings: Settings = cc.storage
+            .and_then(|s| eframe::get_value(s, SETTINGS_KEY))
+            .unwrap_or_default();
 
-        let thread_state = Arc::clone(&state);
-        std::thread::spawn(|| {
-            future::block_on(async { MinidiscThread::minidisc_thread(thread_state, recv).await });
-        });
+        settings.pixels_per_point = settings.pixels_per_point
+            .clamp(*PIXELS_PER_POINT_RANGE.start(), *PIXELS_PER_POINT_RANGE.end());
+        cc.egui_ctx.set_pixels_per_point(settings.pixels_per_point);
+        cc.egui_ctx.set_theme(settings.theme);
 
-        self.md_channel = Some(send);
-        self.md_state = state;
+        // Pick up an upload queue which was never started, minus any files
+        // which have gone since
+        let saved_queue: Vec<SavedUpload> = cc.storage
+            .and_then(|s| eframe::get_value(s, UPLOAD_QUEUE_KEY))
+            .unwrap_or_default();
+        let upload_queue: Vec<QueuedUpload> = saved_queue.into_iter()
+            .filter(|s| s.path.is_file())
+            .map(SavedUpload::restore)
+            .collect();
+
+        Self {
+            device_settings: Arc::new(RwLock::new(settings.device)),
+            settings,
+            upload_queue: (!upload_queue.is_empty()).then_some(upload_queue),
+            ..Default::default()
+        }
     }
-}
 
-#[derive(Default)]
-struct TrackListingTable {
-}
+    /// Connect to the attached device, or let the user pick one if there are
+    /// several.
+    fn choose_device(&mut self) {
+        let devices: Vec<DeviceInfo> = match future::block_on(cross_usb::get_device_list(DEVICE_IDS_CROSSUSB.to_vec())) {
+            Ok(devices) => devices.collect(),
+            Err(e) => {
+                log::warn!("Could not list USB devices: {e}");
+                Vec::new()
+            }
+        };
 
-impl TrackListingTable {
-    fn table(&mut self, ui: &mut egui::Ui, disc: &Disc, playing: Option<usize>, channel: &mut Option<mpsc::Sender<PlayerCommand>>) {
-        let text_height = egui::TextStyle::Body
-            .resolve(ui.style())
-            .size
-            .max(ui.spacing().interact_size.y);
+        if devices.len() > 1 {
+            let devices = devices.into_iter()
+                .map(|d| (future::block_on(device_label(&d)), d))
+                .collect();
+            self.device_picker = Some(devices);
+        } else {
+            self.connect_to_device(devices.into_iter().next());
+        }
+    }
 
-        let available_height = ui.available_height();
-        let mut table = TableBuilder::new(ui)
-            .resizable(false)
-            .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
-            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-            .column(Column::auto())
-            .column(
-                Column::remainder()
-                    .at_least(40.0)
-                    .clip(true),
-            )
-            .column(Column::auto())
-            .column(Column::auto())
-            .column(Column::auto())
-            .min_scrolled_height(0.0)
-            .max_scroll_height(available_height);
+    /// Start the worker thread, connecting to the given device or the first
+    /// one found if there is none.
+    ///
+    /// Any previous worker is told to stop, and the new one waits for it to
+    /// finish so the device is never opened twice.
+    fn connect_to_device(&mut self, device: Option<DeviceInfo>) {
+        self.disconnect();
+        self.md_state.write().unwrap().connecting = true;
+        let (send, recv) = async_channel::unbounded();
 
-        table = table.sense(egui::Sense::click());
+        let previous = self.worker.take();
+        let thread_state = Arc::clone(&self.md_state);
+        let thread_settings = Arc::clone(&self.device_settings);
+        let thread_cache = Arc::clone(&self.disc_cache);
+        self.worker = Some(std::thread::spawn(move || {
+            if let Some(previous) = previous
+                && previous.join().is_err()
+            {
+                log::error!("The previous worker thread panicked");
+            }
 
-        table.header(20.0, |mut header| {
-            header.col(|ui| {
-                ui.strong("#");
-            });
-            header.col(|ui| {
-                ui.strong("Title");
-            });
-            header.col(|ui| {
-                ui.strong("");
-            });
-            header.col(|ui| {
-                ui.strong("Duration");
-            });
-            header.col(|ui| {
-                ui.strong("");
+            future::block_on(async {
+                MinidiscThread::minidisc_thread(thread_state, recv, device, thread_settings, thread_cache).await
             });
-        })
-        .body(|body| {
-            body.rows(text_height, disc.track_count() as usize, |mut row| {
-                let row_track = &disc.tracks()[row.index()];
-
-                let title = if row_track.title().is_empty() {
-                    &"No Title".to_string()
-                } else {
-                    row_track.title()
-                };
+        }));
 
-                if playing.is_some_and(|p| p == row.index()) {
-                    row.set_selected(true);
-                }
+        self.md_channel = Some(send);
+    }
 
-                row.col(|ui| {
-                    ui.label((row_track.index() + 1).to_string());
-                });
-                row.col(|ui| {
-                    ui.add(egui::Label::new(title).truncate());
-                });
-                row.col(|ui| {
-                    ui.label(row_track.encoding().to_string().to_ascii_uppercase());
-                });
-                row.col(|ui| {
-                    ui.label(pretty_duration(row_track.duration().as_duration()));
-                });
-                row.col(|ui| {
-                    ui.label(" ");
-                });
+    /// Replace the player state with a fresh one, detached from any old
+    /// worker thread, keeping the error log.
+    fn reset_state(&mut self) {
+        let mut state = std::mem::take(&mut *self.md_state.write().unwrap());
+        state.reset();
+        self.md_state = Arc::new(RwLock::new(state));
+    }
 
-                if let Some(ch) = channel {
-                    if row.response().double_clicked() {
-                        let _ = ch.send(PlayerCommand::GoToTrack(row.index()));
+    /// The preferences window, opened from the gear button.
+    fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_settings;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong("Transfer");
+                ui.horizontal(|ui| {
+                    ui.label("Default format");
+                    for format in UploadFormat::ALL {
+                        ui.selectable_value(&mut self.settings.upload_format, format, format.to_string())
+                            .on_hover_text(format.description());
                     }
+                });
 
-                    row.response().context_menu(|ui| {
-                        if ui.small_button("Delete").clicked() {
-                            let _ = ch.send(PlayerCommand::Delete(row.index()));
-                        }
-                    });
+                let mut confirm_delete = !self.settings.skip_delete_confirmation;
+                if ui.checkbox(&mut confirm_delete, "Ask before deleting tracks").changed() {
+                    self.settings.skip_delete_confirmation = !confirm_delete;
                 }
+                ui.checkbox(&mut self.settings.keep_awake, "Keep the computer awake while uploading")
+                    .on_hover_text("Sleeping partway through an upload would interrupt it");
+                ui.checkbox(&mut self.settings.confirm_quit, "Ask before quitting while uploading")
+                    .on_hover_text("Quitting partway through an upload may leave a damaged track on the disc");
+                ui.checkbox(&mut self.settings.device.play_after_upload, "Play tracks once they're uploaded")
+                    .on_hover_text("After uploading several, the first of them is played");
+
+                ui.separator();
+                ui.strong("Display");
+                self.display_settings(ui);
+
+                ui.separator();
+                ui.strong("Device");
+                device_settings(ui, &mut self.settings.device);
             });
-        });
-    }
-}
+        self.show_settings = open;
 
+        // The worker only sees its own copy, so keep that up to date
+        *self.device_settings.write().unwrap() = self.settings.device;
+    }
 
-fn pretty_duration(duration: Duration) -> String {
-    format!(
-        "{:02}:{:02}:{:02}",
-        duration.as_secs() / 3600,
-        (duration.as_secs() / 60) % 60,
-        duration.as_secs() % 60,
-    )
-}
+    fn display_settings(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Reset window").on_hover_text("Restore the default window size and scale").clicked() {
+            ui.ctx().set_pixels_per_point(DEFAULT_PIXELS_PER_POINT);
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::InnerSize(DEFAULT_WINDOW_SIZE.into()));
+        }
 
-#[derive(Default)]
-struct PlayerState {
-    connected: bool,
-    reading: bool,
+        ui.horizontal(|ui| {
+            ui.label("UI scale");
 
-    disc_contents: Option<Disc>,
-    device_state: Option<DeviceStatus>,
-    progress: Option<f32>,
-}
+            let scale = ui.ctx().pixels_per_point();
+            let mut new_scale = scale;
+            if ui.small_button("−").clicked() {
+                new_scale -= 0.25;
+            }
+            ui.label(format!("{:.0}%", scale * 100.0));
+            if ui.small_button("+").clicked() {
+                new_scale += 0.25;
+            }
 
-enum PlayerCommand {
-    Disconnect,
-    Playback(Action),
-    Stop,
-    SkipTrack(Direction),
-    GoToTrack(usize),
-    Upload(PathBuf),
-    Delete(usize),
-}
+            let native = ui.ctx().input(|i| i.viewport().native_pixels_per_point);
+            if let Some(native) = native
+                && ui.small_button("Match system").on_hover_text("Use the display's own scale factor").clicked()
+            {
+                new_scale = native;
+            }
 
-struct MinidiscThread {
-    device: NetMDContext,
-    state: Arc<RwLock<PlayerState>>,
-    recv: mpsc::Receiver<PlayerCommand>,
-}
+            let new_scale = new_scale.clamp(*PIXELS_PER_POINT_RANGE.start(), *PIXELS_PER_POINT_RANGE.end());
+            if new_scale != scale {
+                ui.ctx().set_pixels_per_point(new_scale);
+            }
+        });
 
-impl MinidiscThread {
-    async fn minidisc_thread(
-        comm: Arc<RwLock<PlayerState>>,
-        recv: mpsc::Receiver<PlayerCommand>,
-    ) {
-        let usb_dev = cross_usb::get_device(DEVICE_IDS_CROSSUSB.to_vec()).await.unwrap();
-        let md_dev = minidisc::netmd::NetMDContext::new(usb_dev).await.unwrap();
+        ui.horizontal(|ui| {
+            ui.label("Theme");
 
-        let mut new_self = Self {
-            device: md_dev,
-            state: comm,
-            recv,
-        };
+            let mut theme = self.settings.theme;
+            ui.selectable_value(&mut theme, egui::ThemePreference::System, "System");
+            ui.selectable_value(&mut theme, egui::ThemePreference::Light, "☀ Light");
+            ui.selectable_value(&mut theme, egui::ThemePreference::Dark, "🌙 Dark");
 
-        new_self.state.write().unwrap().connected = true;
-        println!("Connected to {:?}", new_self.device.interface().device.device_name());
+            if theme != self.settings.theme {
+                self.settings.theme = theme;
+                ui.ctx().set_theme(theme);
+            }
+        });
 
-        match new_self.command_loop().await {
-            Ok(_) => (),
-            Err(e) => println!("A fatal error occurred: {e}"),
-        }
+        ui.horizontal(|ui| {
+            ui.label("Times");
+            ui.selectable_value(&mut self.settings.duration_format, DurationFormat::Compact, "mm:ss")
+                .on_hover_text("Only show hours when needed");
+            ui.selectable_value(&mut self.settings.duration_format, DurationFormat::Full, "hh:mm:ss")
+                .on_hover_text("Always show hours, so every time is the same width");
+        });
 
-        *new_self.state.write().unwrap() = PlayerState::default();
+        ui.horizontal(|ui| {
+            ui.label("Track list");
+            for density in TableDensity::ALL {
+                ui.selectable_value(&mut self.settings.table_density, density, density.to_string());
+            }
+        });
     }
 
-    async fn get_contents(&mut self) -> Result<()> {
-        self.state.write().unwrap().reading = true;
-        self.state.write().unwrap().disc_contents = Some(self.device.list_content().await?);
-        self.state.write().unwrap().reading = false;
+    /// Tell the worker thread to release the device, and go back to the
+    /// disconnected state.
+    ///
+    /// Dropping the channel also stops the worker if it misses the command.
+    fn disconnect(&mut self) {
+        if let Some(c) = self.md_channel.take() {
+            let _ = c.send_blocking(PlayerCommand::Disconnect);
+        }
 
-        Ok(())
+        self.reset_state();
     }
 
-    async fn command_loop(&mut self) -> Result<()> {
-        self.state.write().unwrap().device_state = Some(self.device.device_status().await?);
-        self.get_contents().await?;
+    /// Show the list of attached devices to pick one to connect to.
+    fn device_picker(&mut self, ctx: &egui::Context) {
+        let Some(devices) = &self.device_picker else {
+            return;
+        };
 
-        let mut state_timer = Instant::now();
-        loop {
-            if let Ok(r) = self.recv.try_recv() {
-                match r {
-                    PlayerCommand::Disconnect => break,
-                    PlayerCommand::Playback(action) => {
-                        self.device.interface_mut().playback_control(action).await?;
-                    },
-                    PlayerCommand::SkipTrack(direction) => {
-                        self.device.interface_mut().track_change(direction).await?;
-                    },
-                    PlayerCommand::GoToTrack(track) => {
-                        self.device.interface_mut().go_to_track(track as u16).await?;
-                        self.device.interface_mut().playback_control(Action::Play).await?;
+        let mut chosen = None;
+        let mut cancelled = false;
+        egui::Window::new("Choose a Device")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                for (i, (label, _)) in devices.iter().enumerate() {
+                    if ui.button(label).clicked() {
+                        chosen = Some(i);
+                    }
+                }
+
+                ui.separator();
+                cancelled = ui.button("Cancel").clicked();
+            });
+
+        if let Some(i) = chosen {
+            let device = self.device_picker.take().unwrap().swap_remove(i).1;
+            self.connect_to_device(Some(device));
+        } else if cancelled {
+            self.device_picker = None;
+        }
+    }
+
+    /// A log of every command the app has run on the device, like uploading
+    /// or renaming a track, and how it went, for diagnosing problems with
+    /// particular models.
+    fn command_log(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("command_log")
+            .resizable(true)
+            .frame(egui::Frame::side_top_panel(&ctx.style()).fill(egui::Color32::from_gray(16)))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Command Log").strong().color(egui::Color32::from_gray(220)));
+                    if ui.small_button("Clear").clicked() {
+                        self.md_state.write().unwrap().command_log.clear();
+                    }
+                });
+
+                egui::ScrollArea::vertical().stick_to_bottom(true).auto_shrink(false).show(ui, |ui| {
+                    for entry in &self.md_state.read().unwrap().command_log {
+                        let secs = entry.time.as_secs_f32();
+                        let (result, color) = match &entry.result {
+                            Ok(()) => ("ok".to_string(), egui::Color32::from_gray(180)),
+                            Err(e) => (e.clone(), egui::Color32::from_rgb(240, 110, 100)),
+                        };
+
+                        let line = format!("[{:02}:{:06.3}] {} → {result}", (secs / 60.0) as u32, secs % 60.0, entry.command);
+                        ui.label(egui::RichText::new(line).monospace().color(color));
+                    }
+                });
+            });
+    }
+
+    /// Buttons for marking the start and end of an A-B loop at the current
+    /// position, and for clearing it.
+    fn ab_loop_controls(&mut self, ui: &mut egui::Ui) {
+        let (position, ab_loop) = {
+            let state = self.md_state.read().unwrap();
+            let position = state.device_state
+                .filter(|s| s.state == Some(OS::Playing))
+                .map(|s| (s.track, Duration::from(s.time)));
+            (position, state.ab_loop)
+        };
+
+        if ui.add_enabled(position.is_some(), egui::SelectableLabel::new(self.loop_start.is_some() || ab_loop.is_some(), "A"))
+            .on_hover_text("Start a loop here")
+            .clicked()
+        {
+            self.loop_start = position;
+            self.send_command(PlayerCommand::SetLoop(None));
+        }
+
+        let end = position.zip(self.loop_start)
+            .filter(|((track, time), (start_track, start))| track == start_track && time > start);
+        if ui.add_enabled(end.is_some(), egui::SelectableLabel::new(ab_loop.is_some(), "B"))
+            .on_hover_text("End the loop here")
+            .clicked()
+            && let Some(((track, end), (_, start))) = end
+        {
+            self.loop_start = None;
+            self.send_command(PlayerCommand::SetLoop(Some(AbLoop { track, start, end })));
+        }
+
+        if (self.loop_start.is_some() || ab_loop.is_some())
+            && ui.button("✖").on_hover_text("Clear the loop").clicked()
+        {
+            self.loop_start = None;
+            self.send_command(PlayerCommand::SetLoop(None));
+        }
+    }
+
+    /// Stop the computer from sleeping while an upload is underway, if the
+    /// user wants that.
+    fn update_keep_awake(&mut self) {
+        let uploading = {
+            let state = self.md_state.read().unwrap();
+            state.progress.is_some() || state.preparing_transfer || state.queue_position.is_some()
+        };
+
+        if !(uploading && self.settings.keep_awake) {
+            self.keep_awake = None;
+        } else if self.keep_awake.is_none() {
+            // Kept even if it fails, so it's only tried once per upload
+            let awake = keepawake::Builder::default()
+                .idle(true)
+                .reason("Uploading to a MiniDisc")
+                .app_name("Rust Minidisc Application")
+                .create()
+                .map_err(|e| e.to_string());
+
+            if let Err(e) = &awake {
+                log::warn!("Could not keep the computer awake: {e}");
+            }
+            self.keep_awake = Some(awake);
+        }
+    }
+
+    /// Whether the disc in the device can't be edited.
+    fn write_protected(&self) -> bool {
+        let state = self.md_state.read().unwrap();
+        state.disc_contents.is_some() && state.write_protected
+    }
+
+    /// Whether a track is being sent to the device, or about to be.
+    fn transferring(&self) -> bool {
+        let state = self.md_state.read().unwrap();
+        state.progress.is_some() || state.preparing_transfer || state.queue_position.is_some()
+    }
+
+    /// Why new uploads can't be started right now, if they can't.
+    fn upload_blocked(&self) -> Option<&'static str> {
+        if self.write_protected() {
+            Some(WRITE_PROTECTED_HINT)
+        } else if self.transferring() {
+            Some(TRANSFER_IN_PROGRESS_HINT)
+        } else {
+            None
+        }
+    }
+
+    /// Send a command to the worker thread, if connected.
+    fn send_command(&self, command: PlayerCommand) {
+        if let Some(c) = &self.md_channel {
+            let _ = c.send_blocking(command);
+        }
+    }
+
+    /// Pause if the device is playing, otherwise carry on from the current
+    /// position. Selecting a track doesn't change where this plays from, that
+    /// needs a double-click or Play From Here.
+    fn play_pause(&self) {
+        let dev_state = self.md_state.read().unwrap().device_state;
+        if dev_state.is_some_and(|s| s.state.is_some_and(|s| s == OS::Playing)) {
+            self.send_command(PlayerCommand::Playback(Action::Pause));
+        } else {
+            self.send_command(PlayerCommand::Playback(Action::Play));
+        }
+    }
+
+    /// Handle the transport control shortcuts, unless a text field is being
+    /// typed in.
+    fn keyboard_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        let (play_pause, stop, previous, next, undo, upload, command_log, quit) = ctx.input_mut(|i| (
+            i.consume_shortcut(&PLAY_PAUSE_SHORTCUT),
+            i.consume_shortcut(&STOP_SHORTCUT),
+            i.consume_shortcut(&PREVIOUS_SHORTCUT),
+            i.consume_shortcut(&NEXT_SHORTCUT),
+            i.consume_shortcut(&UNDO_SHORTCUT),
+            i.consume_shortcut(&UPLOAD_SHORTCUT),
+            i.consume_shortcut(&COMMAND_LOG_SHORTCUT),
+            i.consume_shortcut(&QUIT_SHORTCUT),
+        ));
+
+        if play_pause {
+            self.play_pause();
+        }
+        if stop {
+            self.send_command(PlayerCommand::Stop);
+        }
+        if previous {
+            self.queue_skip(-1);
+        }
+        if next {
+            self.queue_skip(1);
+        }
+        if undo {
+            self.undo();
+        }
+        if upload && self.md_channel.is_some() && self.upload_blocked().is_none() {
+            let paths = self.pick_upload_files();
+            self.start_upload(paths);
+        }
+        if command_log {
+            self.show_command_log = !self.show_command_log;
+        }
+        if quit {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+
+    fn file_menu(&mut self, ui: &mut egui::Ui) {
+        let ctx = ui.ctx().clone();
+        let connected = self.md_channel.is_some();
+        let has_disc = self.md_state.read().unwrap().disc_contents.is_some();
+        let upload_blocked = self.upload_blocked();
+
+        if ui.add_enabled(connected && upload_blocked.is_none(), egui::Button::new("Upload…").shortcut_text(ctx.format_shortcut(&UPLOAD_SHORTCUT)))
+            .on_disabled_hover_text(upload_blocked.unwrap_or_default())
+            .clicked()
+        {
+            ui.close_menu();
+            let paths = self.pick_upload_files();
+            self.start_upload(paths);
+        }
+        if ui.add_enabled(connected && upload_blocked.is_none(), egui::Button::new("Import Playlist…"))
+            .on_hover_text("Upload the files in an M3U or JSON playlist")
+            .on_disabled_hover_text(upload_blocked.unwrap_or_default())
+            .clicked()
+        {
+            ui.close_menu();
+            self.import_playlist();
+        }
+        if ui.add_enabled(has_disc, egui::Button::new("Export Listing…"))
+            .on_hover_text("Save the track listing as CSV or JSON")
+            .clicked()
+        {
+            ui.close_menu();
+            self.export_listing();
+        }
+
+        ui.separator();
+
+        if ui.add(egui::Button::new("Quit").shortcut_text(ctx.format_shortcut(&QUIT_SHORTCUT))).clicked() {
+            ui.close_menu();
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+
+    fn device_menu(&mut self, ui: &mut egui::Ui) {
+        let connected = self.md_channel.is_some();
+        let (has_disc, can_eject) = {
+            let state = self.md_state.read().unwrap();
+            (state.disc_contents.is_some(), state.can_eject)
+        };
+
+        if ui.add_enabled(!connected, egui::Button::new("Connect…")).clicked() {
+            ui.close_menu();
+            self.choose_device();
+        }
+        if ui.add_enabled(connected, egui::Button::new("Disconnect")).clicked() {
+            ui.close_menu();
+            self.disconnect();
+        }
+        if ui.add_enabled(connected, egui::Button::new("Refresh")).clicked() {
+            ui.close_menu();
+            self.send_command(PlayerCommand::Refresh);
+        }
+        if ui.add_enabled(connected, egui::Button::new("Device Info")).clicked() {
+            ui.close_menu();
+            self.show_device_info = true;
+        }
+
+        ui.separator();
+
+        if ui.add_enabled(has_disc && !self.write_protected(), egui::Button::new("Find and Replace Titles…"))
+            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+            .clicked()
+        {
+            ui.close_menu();
+            self.find_replace = Some(FindReplace::default());
+        }
+        if ui.add_enabled(has_disc && !self.write_protected(), egui::Button::new("Number Titles…"))
+            .on_hover_text("Put track numbers in every title")
+            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+            .clicked()
+        {
+            ui.close_menu();
+            let track_count = self.md_state.read().unwrap().disc_contents.as_ref().map_or(0, |dc| dc.track_count() as usize);
+            self.numbering = Some(((0..track_count).collect(), Numbering::default()));
+        }
+        if ui.add_enabled(has_disc && can_eject, egui::Button::new("Eject")).clicked() {
+            ui.close_menu();
+            self.send_command(PlayerCommand::Eject);
+        }
+        if ui.add_enabled(has_disc && !self.write_protected(), egui::Button::new("Erase Disc…"))
+            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+            .clicked()
+        {
+            ui.close_menu();
+            self.erase_confirmation = Some(String::new());
+        }
+    }
+
+    fn help_menu(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Keyboard Shortcuts").clicked() {
+            ui.close_menu();
+            self.show_shortcuts = true;
+        }
+        if ui.button("About").clicked() {
+            ui.close_menu();
+            self.show_about = true;
+        }
+    }
+
+    /// Details of the connected device, for troubleshooting and bug reports.
+    fn device_info(&mut self, ctx: &egui::Context) {
+        if !self.md_state.read().unwrap().connected {
+            self.show_device_info = false;
+        }
+
+        let details = {
+            let state = self.md_state.read().unwrap();
+            let yes_no = |b: bool| if b { "Yes" } else { "No" };
+
+            vec![
+                ("Model", state.device_name.clone().unwrap_or_else(|| "Unknown".to_string())),
+                ("USB ID", state.usb_id.map_or("Unknown".to_string(), |(v, p)| format!("{v:04x}:{p:04x}"))),
+                ("Motorized eject", yes_no(state.can_eject).to_string()),
+                ("Disc present", yes_no(state.device_state.is_some_and(|s| s.disc_present)).to_string()),
+                ("App version", env!("CARGO_PKG_VERSION").to_string()),
+            ]
+        };
+
+        egui::Window::new("Device Info")
+            .open(&mut self.show_device_info)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("device_info_grid").num_columns(2).striped(true).show(ui, |ui| {
+                    for (name, value) in &details {
+                        ui.label(*name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+
+                if ui.button("Copy").on_hover_text("Copy these details, to paste into a bug report").clicked() {
+                    let text: Vec<String> = details.iter().map(|(name, value)| format!("{name}: {value}")).collect();
+                    ui.ctx().copy_text(text.join("\n"));
+                }
+            });
+    }
+
+    /// The windows opened from the help menu.
+    fn help_windows(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Keyboard Shortcuts")
+            .open(&mut self.show_shortcuts)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts_grid").num_columns(2).striped(true).show(ui, |ui| {
+                    for (name, shortcut) in SHORTCUTS {
+                        ui.label(*name);
+                        ui.monospace(ctx.format_shortcut(shortcut));
+                        ui.end_row();
+                    }
+                });
+            });
+
+        egui::Window::new("About")
+            .open(&mut self.show_about)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.image(include_image!("./MiniDisc192.png"));
+                    ui.heading("Minidisc Manager");
+                    ui.label(format!("Version {}", env!("CARGO_PKG_VERSION")));
+                    ui.label("Manage the tracks on a NetMD MiniDisc device");
+                });
+            });
+    }
+
+    /// Add a skip forwards or backwards to the ones waiting to be sent, so
+    /// quick presses move several tracks in one go.
+    fn queue_skip(&mut self, tracks: isize) {
+        let pending = self.pending_skip.map_or(0, |(n, _)| n);
+        self.pending_skip = Some((pending + tracks, Instant::now()));
+    }
+
+    /// Send the waiting skips once the user stops pressing the buttons.
+    fn send_pending_skip(&mut self) {
+        if let Some((tracks, last_press)) = self.pending_skip
+            && last_press.elapsed() >= SKIP_DEBOUNCE
+        {
+            self.pending_skip = None;
+            if tracks != 0 {
+                self.send_command(PlayerCommand::SkipTracks(tracks));
+            }
+        }
+    }
+
+    /// Reverse the most recent edit, if there is one.
+    fn undo(&self) {
+        let undo = self.md_state.write().unwrap().undo.take();
+        if let Some(undo) = undo {
+            self.send_command(undo.command);
+        }
+    }
+
+    /// The elapsed and total playback time, for either the current track or
+    /// the whole disc.
+    fn playback_times(&self) -> (Duration, Duration) {
+        let state = self.md_state.read().unwrap();
+        let (Some((track, length, elapsed)), Some(dc)) = (state.current_track(), &state.disc_contents) else {
+            return (Duration::ZERO, Duration::ZERO);
+        };
+
+        let tracks = dc.tracks();
+        if self.show_disc_time {
+            let before: Duration = tracks[..track].iter().map(|t| t.duration().as_duration()).sum();
+            let total = tracks.iter().map(|t| t.duration().as_duration()).sum();
+            (before + elapsed, total)
+        } else {
+            (elapsed, length)
+        }
+    }
+
+    /// How long until playback reaches the end of the last track, if there
+    /// is a track to play.
+    fn disc_remaining(&self) -> Option<Duration> {
+        let state = self.md_state.read().unwrap();
+        let (index, length, elapsed) = state.current_track()?;
+        let after: Duration = state.disc_contents.as_ref()?.tracks()[index + 1..].iter()
+            .map(|t| t.duration().as_duration())
+            .sum();
+
+        Some(length.saturating_sub(elapsed) + after)
+    }
+
+    /// Show the disc title, which can be clicked to edit it.
+    fn disc_title(&mut self, ui: &mut egui::Ui) {
+        let edit_id = egui::Id::new("disc_title_edit");
+
+        let state = self.md_state.read().unwrap();
+        let Some(dc) = &state.disc_contents else {
+            self.disc_title_edit = None;
+            return;
+        };
+
+        let Some(title) = self.disc_title_edit.as_mut() else {
+            let label = if dc.title().is_empty() {
+                "No Title"
+            } else {
+                dc.title().as_str()
+            };
+
+            if state.write_protected {
+                ui.add(egui::Label::new(label).truncate()).on_hover_text(WRITE_PROTECTED_HINT);
+                return;
+            }
+
+            let response = ui.add(egui::Label::new(label).truncate().sense(egui::Sense::click()))
+                .on_hover_text("Click to rename the disc");
+            if response.clicked() {
+                self.disc_title_edit = Some(dc.title().clone());
+                ui.memory_mut(|m| m.request_focus(edit_id));
+            }
+            return;
+        };
+
+        let response = ui.add(
+            egui::TextEdit::singleline(title)
+                .id(edit_id)
+                .hint_text("No Title")
+                .desired_width(200.0)
+        );
+
+        if !titles_fit_in_toc(dc, title) {
+            ui.colored_label(ui.visuals().warn_fg_color, "⚠")
+                .on_hover_text("This title may be too long to fit in the disc's table of contents");
+        }
+        if let Some(hint) = title_change_hint(title) {
+            ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text(hint);
+        }
+
+        if response.lost_focus() {
+            if ui.input(|i| i.key_pressed(egui::Key::Enter))
+                && let Some(c) = self.md_channel.as_mut()
+            {
+                let _ = c.send_blocking(PlayerCommand::SetDiscTitle(title.clone()));
+            }
+            self.disc_title_edit = None;
+        }
+    }
+
+    /// Upload the given files, asking for a title first if there is only one
+    /// and adding them to the upload queue if there are more.
+    fn start_upload(&mut self, mut paths: Vec<PathBuf>) {
+        if paths.len() == 1 && self.upload_queue.is_none() {
+            self.upload_dialog = Some(UploadDialog::new(paths.remove(0)));
+        } else {
+            self.queue_uploads(paths.into_iter().map(UploadDialog::new));
+        }
+    }
+
+    /// Add files to the upload queue for the user to look over before
+    /// starting it.
+    fn queue_uploads(&mut self, uploads: impl IntoIterator<Item = UploadDialog>) {
+        let format = self.settings.upload_format;
+        let queued: Vec<QueuedUpload> = uploads.into_iter()
+            .map(|details| QueuedUpload { details, format })
+            .collect();
+
+        if !queued.is_empty() {
+            self.upload_queue.get_or_insert_default().extend(queued);
+        }
+    }
+
+    /// A panel listing the files waiting to be uploaded, which can be
+    /// reordered, retitled and removed before the upload starts.
+    fn upload_queue(&mut self, ctx: &egui::Context) {
+        let transferring = self.transferring();
+        let connected = self.md_channel.is_some();
+        let Some(queue) = self.upload_queue.as_mut() else {
+            return;
+        };
+
+        let mut start = false;
+        let mut clear = false;
+        let mut remove = None;
+        let mut moved = None;
+        egui::SidePanel::right("upload_queue").resizable(true).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.strong("Upload Queue");
+                ui.weak(format!("{} files", queue.len()));
+            });
+
+            // Changing this sets the volume of every file that can be adjusted
+            if let Some(first) = queue.iter().find(|q| q.details.is_adjustable()) {
+                let mut level = first.details.level;
+                ui.horizontal(|ui| {
+                    ui.label("Volume for all");
+                    level_picker(ui, &mut level);
+                });
+                if level != first.details.level {
+                    for item in queue.iter_mut().filter(|q| q.details.is_adjustable()) {
+                        item.details.level = level;
+                    }
+                }
+            }
+            ui.separator();
+
+            let indicator_color = ui.visuals().selection.bg_fill;
+            egui::ScrollArea::vertical().auto_shrink(false).max_height(ui.available_height() - 40.0).show(ui, |ui| {
+                for (i, item) in queue.iter_mut().enumerate() {
+                    let row = ui.push_id(i, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.dnd_drag_source(egui::Id::new(("upload_queue_item", i)), i, |ui| {
+                                ui.label("☰");
+                            }).response.on_hover_cursor(egui::CursorIcon::Grab).on_hover_text("Drag to reorder");
+
+                            egui::ComboBox::from_id_salt("format")
+                                .width(50.)
+                                .selected_text(item.format.to_string())
+                                .show_ui(ui, |ui| {
+                                    for format in UploadFormat::ALL {
+                                        ui.selectable_value(&mut item.format, format, format.to_string())
+                                            .on_hover_text(format.description());
+                                    }
+                                });
+
+                            if ui.small_button("✖").on_hover_text("Remove from the queue").clicked() {
+                                remove = Some(i);
+                            }
+
+                            ui.add(egui::TextEdit::singleline(&mut item.details.title).hint_text(file_stem(&item.details.path)))
+                                .on_hover_text(item.details.path.display().to_string());
+
+                            if item.details.is_adjustable() {
+                                ui.menu_button("🔊", |ui| level_picker(ui, &mut item.details.level))
+                                    .response
+                                    .on_hover_text("Volume");
+                            }
+
+                            if let Some(problem) = item.details.problem(item.format) {
+                                ui.colored_label(ui.visuals().error_fg_color, "⚠").on_hover_text(problem);
+                            } else if let Some(hint) = title_change_hint(&item.details.title) {
+                                ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text(hint);
+                            }
+                        }).response
+                    }).inner;
+
+                    // Show where a dragged file would land, above or below this one
+                    if let Some(pos) = ui.ctx().pointer_latest_pos()
+                        && row.rect.y_range().contains(pos.y)
+                        && row.dnd_hover_payload::<usize>().is_some()
+                    {
+                        let before = pos.y < row.rect.center().y;
+                        let y = if before { row.rect.top() } else { row.rect.bottom() };
+                        ui.painter().hline(row.rect.x_range(), y, egui::Stroke::new(2.0, indicator_color));
+
+                        if let Some(from) = row.dnd_release_payload::<usize>() {
+                            let target = if before { i } else { i + 1 };
+                            // Removing the file first shifts everything after it up one
+                            moved = Some((*from, if target > *from { target - 1 } else { target }));
+                        }
+                    }
+                }
+            });
+
+            ui.separator();
+
+            let problems = queue.iter().any(|q| q.details.problem(q.format).is_some());
+            ui.horizontal(|ui| {
+                start = ui.add_enabled(!queue.is_empty() && !problems && !transferring && connected, egui::Button::new("Start"))
+                    .on_disabled_hover_text(if !connected {
+                        NOT_CONNECTED_HINT
+                    } else if transferring {
+                        TRANSFER_IN_PROGRESS_HINT
+                    } else {
+                        "Fix or remove the files marked with ⚠ first"
+                    })
+                    .clicked();
+                clear = ui.button("Clear Queue").on_hover_text("Remove every file without uploading them").clicked();
+            });
+        });
+
+        if let Some((from, to)) = moved {
+            let item = queue.remove(from);
+            queue.insert(to, item);
+        }
+        if let Some(i) = remove {
+            queue.remove(i);
+        }
+
+        if start && let Some(c) = &self.md_channel {
+            let jobs = queue.iter().map(|q| q.details.to_job(q.format)).collect();
+            // Keep the queue if the worker has gone, so nothing is lost
+            if c.send_blocking(PlayerCommand::UploadQueue(jobs)).is_ok() {
+                self.upload_queue = None;
+            }
+        } else if clear {
+            self.upload_queue = None;
+        }
+    }
+
+    /// Upload any files dropped onto the window, and show an overlay while
+    /// they are hovering over it.
+    fn dropped_files(&mut self, ctx: &egui::Context) {
+        if self.md_channel.is_none() || self.upload_blocked().is_some() {
+            return;
+        }
+
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("drop_overlay"),
+            ));
+
+            let screen_rect = ctx.screen_rect();
+            painter.rect_filled(screen_rect, 0.0, egui::Color32::from_black_alpha(192));
+            painter.text(
+                screen_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop to upload",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                egui::Color32::WHITE,
+            );
+        }
+
+        let paths: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter()
+                .filter_map(|f| f.path.clone())
+                .filter(|p| is_uploadable(p))
+                .collect()
+        });
+
+        self.start_upload(paths);
+    }
+
+    /// Show the dialog for editing a track's details before it is uploaded.
+    fn upload_dialog(&mut self, ctx: &egui::Context) {
+        let transferring = self.transferring();
+        let connected = self.md_channel.is_some();
+        let Some(dialog) = self.upload_dialog.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Upload Track")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("upload_dialog_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("File");
+                    ui.label(dialog.path.display().to_string());
+                    ui.end_row();
+
+                    ui.label("Title");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut dialog.title);
+                        if let Some(hint) = title_change_hint(&dialog.title) {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text(hint);
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Full-width title");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut dialog.full_width_title)
+                            .on_hover_text("Optional, used for Japanese titles");
+                        if let Some(hint) = title_change_hint(&dialog.full_width_title) {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text(hint);
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Format");
+                    egui::ComboBox::from_id_salt("upload_dialog_format")
+                        .width(60.)
+                        .selected_text(self.settings.upload_format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in UploadFormat::ALL {
+                                ui.selectable_value(&mut self.settings.upload_format, format, format.to_string())
+                                    .on_hover_text(format.description());
+                            }
+                        })
+                        .response
+                        .on_hover_text(self.settings.upload_format.description());
+                    ui.end_row();
+
+                    let free = self.md_state.read().unwrap().disc_contents.as_ref().map(disc_free_time);
+                    ui.label("Disc space");
+                    egui::Grid::new("upload_dialog_space").num_columns(3).show(ui, |ui| {
+                        for format in UploadFormat::ALL {
+                            let length = dialog.length(format);
+                            let available = free.map(|f| f * format.time_multiplier());
+                            let fits = length.zip(available).is_none_or(|(l, a)| l <= a);
+
+                            let name = egui::RichText::new(format.to_string());
+                            if format == self.settings.upload_format {
+                                ui.label(name.strong());
+                            } else {
+                                ui.label(name);
+                            }
+
+                            let uses = match length {
+                                Some(length) => format!("uses {}", pretty_duration(length, self.settings.duration_format)),
+                                None => "uses ?".to_string(),
+                            };
+                            if fits {
+                                ui.label(uses);
+                            } else {
+                                ui.colored_label(ui.visuals().error_fg_color, uses);
+                            }
+
+                            match available {
+                                Some(available) => ui.weak(format!("of {} free", pretty_duration(available, self.settings.duration_format))),
+                                None => ui.weak("no disc"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Audio");
+                    match &dialog.wav_spec {
+                        Some(Ok(spec)) => ui.label(format!("{} WAV", describe_wav(spec))),
+                        Some(Err(_)) => ui.label("Unreadable WAV"),
+                        None => ui.label("Raw data, sent as it is"),
+                    };
+                    ui.end_row();
+
+                    if dialog.is_adjustable() {
+                        ui.label("Volume");
+                        level_picker(ui, &mut dialog.level);
+                        ui.end_row();
+                    }
+                });
+
+                let problem = dialog.problem(self.settings.upload_format);
+                if let Some(problem) = &problem {
+                    ui.colored_label(ui.visuals().error_fg_color, problem);
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    confirmed = ui.add_enabled(problem.is_none() && !transferring && connected, egui::Button::new("Upload"))
+                        .on_disabled_hover_text(match &problem {
+                            Some(problem) => problem.as_str(),
+                            None if !connected => NOT_CONNECTED_HINT,
+                            None => TRANSFER_IN_PROGRESS_HINT,
+                        })
+                        .clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if confirmed && let Some(c) = &self.md_channel {
+            let job = dialog.to_job(self.settings.upload_format);
+            // Leave the dialog open if the worker has gone, so nothing is lost
+            if c.send_blocking(PlayerCommand::Upload(job)).is_ok() {
+                self.upload_dialog = None;
+            }
+        } else if !open || cancelled {
+            self.upload_dialog = None;
+        }
+    }
+
+    /// Find and replace text in every track title, showing which titles will
+    /// change before renaming them.
+    fn find_replace_window(&mut self, ctx: &egui::Context) {
+        let Some(find_replace) = self.find_replace.as_mut() else {
+            return;
+        };
+
+        let titles: Vec<TrackTitles> = self.md_state.read().unwrap().disc_contents.as_ref()
+            .map(|dc| dc.tracks().iter().map(track_titles).collect())
+            .unwrap_or_default();
+
+        let mut open = true;
+        let mut apply = None;
+        let mut cancelled = false;
+        egui::Window::new("Find and Replace Titles")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("find_replace_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Find");
+                    ui.text_edit_singleline(&mut find_replace.find);
+                    ui.end_row();
+
+                    ui.label("Replace with");
+                    ui.text_edit_singleline(&mut find_replace.replace);
+                    ui.end_row();
+                });
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut find_replace.match_case, "Match case");
+                    ui.checkbox(&mut find_replace.regex, "Regular expression")
+                        .on_hover_text("Use $1, $2… in the replacement for the parts in brackets");
+                });
+
+                ui.separator();
+
+                let renames = find_replace.renames(&titles);
+                match &renames {
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, "Invalid pattern").on_hover_text(e);
+                    }
+                    Ok(renames) if renames.is_empty() => {
+                        ui.weak("No titles will change");
+                    }
+                    Ok(renames) => {
+                        ui.label(format!("{} of {} titles will change", renames.len(), titles.len()));
+                        rename_preview(ui, &titles, renames);
+                    }
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let can_apply = renames.as_ref().is_ok_and(|r| !r.is_empty());
+                    if ui.add_enabled(can_apply, egui::Button::new("Rename")).clicked() {
+                        apply = renames.ok();
+                    }
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if let Some(renames) = apply {
+            self.find_replace = None;
+            self.send_command(PlayerCommand::RenameTracks(renames));
+        } else if !open || cancelled {
+            self.find_replace = None;
+        }
+    }
+
+    /// Number the titles of some tracks from a template, showing which
+    /// titles will change before renaming them.
+    fn numbering_window(&mut self, ctx: &egui::Context) {
+        if let Some(tracks) = self.track_listing_table.pending_numbering.take() {
+            self.numbering = Some((tracks, Numbering::default()));
+        }
+
+        let Some((tracks, numbering)) = self.numbering.as_mut() else {
+            return;
+        };
+
+        let titles: Vec<TrackTitles> = self.md_state.read().unwrap().disc_contents.as_ref()
+            .map(|dc| dc.tracks().iter().map(track_titles).collect())
+            .unwrap_or_default();
+
+        let mut open = true;
+        let mut apply = None;
+        let mut cancelled = false;
+        egui::Window::new("Number Titles")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("numbering_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Title");
+                    ui.text_edit_singleline(&mut numbering.template)
+                        .on_hover_text("{n} is the track's number and {title} its current title");
+                    ui.end_row();
+
+                    ui.label("Untitled tracks");
+                    ui.text_edit_singleline(&mut numbering.untitled_template)
+                        .on_hover_text("Used for tracks without a title, {n} is the track's number");
+                    ui.end_row();
+
+                    ui.label("Start at");
+                    ui.add(egui::DragValue::new(&mut numbering.start).range(0..=999));
+                    ui.end_row();
+
+                    ui.label("Digits");
+                    ui.add(egui::DragValue::new(&mut numbering.digits).range(1..=3))
+                        .on_hover_text("Numbers shorter than this are padded with zeros");
+                    ui.end_row();
+                });
+
+                ui.checkbox(&mut numbering.replace_numbers, "Replace existing numbers")
+                    .on_hover_text("Take off any number a title already starts with, such as \"03 - \"");
+
+                ui.separator();
+
+                let current: Vec<(usize, TrackTitles)> = tracks.iter()
+                    .filter_map(|&i| titles.get(i).map(|t| (i, t.clone())))
+                    .collect();
+                let renames = numbering.renames(&current);
+                if renames.is_empty() {
+                    ui.weak("No titles will change");
+                } else {
+                    ui.label(format!("{} of {} titles will change", renames.len(), current.len()));
+                    rename_preview(ui, &titles, &renames);
+                }
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!renames.is_empty(), egui::Button::new("Rename")).clicked() {
+                        apply = Some(renames);
+                    }
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if let Some(renames) = apply {
+            self.numbering = None;
+            self.send_command(PlayerCommand::RenameTracks(renames));
+        } else if !open || cancelled {
+            self.numbering = None;
+        }
+    }
+
+    /// Ask the user to confirm a track deletion requested from the table.
+    fn delete_confirmation(&mut self, ctx: &egui::Context) {
+        if let Some(tracks) = self.track_listing_table.pending_delete.take() {
+            if self.settings.skip_delete_confirmation {
+                self.delete_tracks(tracks);
+            } else {
+                self.delete_confirmation = Some(DeleteConfirmation { tracks, dont_ask_again: false });
+            }
+        }
+
+        let Some(confirmation) = self.delete_confirmation.as_mut() else {
+            return;
+        };
+
+        let message = if let &[index] = confirmation.tracks.as_slice() {
+            let title = self.md_state.read().unwrap().disc_contents.as_ref()
+                .and_then(|dc| dc.tracks().get(index))
+                .map(|t| t.title().clone())
+                .unwrap_or_default();
+
+            format!("Track {}: {title}\n\nThis track will be permanently erased from the disc.", index + 1)
+        } else {
+            format!("{} tracks will be permanently erased from the disc.", confirmation.tracks.len())
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Delete Tracks?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(message);
+                ui.label("This can't be undone.");
+                ui.checkbox(&mut confirmation.dont_ask_again, "Don't ask again")
+                    .on_hover_text("This can be turned back on in Settings");
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Delete").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if confirmed {
+            let confirmation = self.delete_confirmation.take().unwrap();
+            self.settings.skip_delete_confirmation = confirmation.dont_ask_again;
+            self.delete_tracks(confirmation.tracks);
+        } else if cancelled {
+            self.delete_confirmation = None;
+        }
+    }
+
+    fn delete_tracks(&mut self, tracks: Vec<usize>) {
+        // Afterwards, select the track that moves up into the first gap, if
+        // there is one
+        let track_count = self.md_state.read().unwrap().disc_contents.as_ref()
+            .map_or(0, |dc| dc.track_count() as usize);
+        let selected = &mut self.track_listing_table.selected;
+        selected.clear();
+        selected.extend(tracks.iter().copied().min().filter(|&first| first < track_count.saturating_sub(tracks.len())));
+
+        if let Some(c) = self.md_channel.as_mut() {
+            let _ = c.send_blocking(PlayerCommand::Delete(tracks));
+        }
+    }
+
+    /// Ask the user to type a confirmation before erasing the whole disc.
+    fn erase_confirmation(&mut self, ctx: &egui::Context) {
+        let Some(typed) = self.erase_confirmation.as_mut() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Erase Disc?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("Every track on the disc will be permanently erased. This can't be undone.");
+                ui.label(format!("Type {ERASE_CONFIRMATION_TEXT} to confirm."));
+                ui.text_edit_singleline(typed);
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let matches = typed.as_str() == ERASE_CONFIRMATION_TEXT;
+                    confirmed = ui.add_enabled(matches, egui::Button::new("Erase")).clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            });
+
+        if confirmed {
+            self.erase_confirmation = None;
+            self.send_command(PlayerCommand::EraseDisc);
+        } else if cancelled {
+            self.erase_confirmation = None;
+        }
+    }
+
+    /// Hold off closing the window while a transfer is running, which could
+    /// leave a damaged track on the disc, until the user decides whether to
+    /// stop it first.
+    fn quit_confirmation(&mut self, ctx: &egui::Context) {
+        let transferring = self.transferring();
+        if ctx.input(|i| i.viewport().close_requested())
+            && transferring
+            && self.settings.confirm_quit
+            && self.quit != Some(QuitState::Quitting)
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            if self.quit.is_none() {
+                self.quit = Some(QuitState::Asking);
+            }
+        }
+
+        match self.quit {
+            None | Some(QuitState::Quitting) => return,
+            Some(QuitState::Cancelling) if !transferring => {
+                self.quit = Some(QuitState::Quitting);
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                return;
+            }
+            Some(QuitState::Asking) if !transferring => {
+                // The transfer finished while the user was deciding
+                self.quit = None;
+                return;
+            }
+            _ => (),
+        }
+
+        let mut keep_going = false;
+        egui::Window::new("Quit While Uploading?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if self.quit == Some(QuitState::Cancelling) {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Stopping the upload, the window will close once it has stopped…");
+                    });
+                    if ui.button("Quit Now").on_hover_text("Don't wait, the track being uploaded may be damaged").clicked() {
+                        self.quit = Some(QuitState::Quitting);
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                    return;
+                }
+
+                ui.label("A track is still being uploaded. Quitting now may leave a damaged track on the disc.");
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Stop Upload and Quit").clicked() {
+                        self.quit = Some(QuitState::Cancelling);
+                        self.send_command(PlayerCommand::CancelUpload);
+                    }
+                    keep_going = ui.button("Keep Uploading").clicked();
+                });
+            });
+
+        if keep_going {
+            self.quit = None;
+        }
+    }
+
+    /// Show the errors collected from the last upload queue, if any.
+    fn upload_errors(&mut self, ctx: &egui::Context) {
+        if self.md_state.read().unwrap().upload_errors.is_empty() {
+            return;
+        }
+
+        let mut dismissed = false;
+        egui::Window::new("Some Uploads Failed")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                for error in &self.md_state.read().unwrap().upload_errors {
+                    ui.label(error);
+                }
+
+                ui.separator();
+                dismissed = ui.button("Dismiss").clicked();
+            });
+
+        if dismissed {
+            self.md_state.write().unwrap().upload_errors.clear();
+        }
+    }
+
+    /// The files an imported playlist names which couldn't be found, so they
+    /// were left out of the upload queue.
+    fn playlist_missing(&mut self, ctx: &egui::Context) {
+        if self.playlist_missing.is_empty() {
+            return;
+        }
+
+        let title = match self.playlist_missing.len() {
+            1 => "1 Playlist Entry Not Found".to_string(),
+            n => format!("{n} Playlist Entries Not Found"),
+        };
+
+        let mut dismissed = false;
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                for path in &self.playlist_missing {
+                    ui.label(path.display().to_string());
+                }
+
+                ui.separator();
+                dismissed = ui.button("Dismiss").clicked();
+            });
+
+        if dismissed {
+            self.playlist_missing.clear();
+        }
+    }
+
+    /// Open a file dialog to pick audio files to upload, starting in the
+    /// last used directory.
+    fn pick_upload_files(&mut self) -> Vec<PathBuf> {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Upload Tracks")
+            .add_filter("Audio", UPLOAD_EXTENSIONS);
+
+        if let Some(dir) = &self.settings.last_upload_dir {
+            dialog = dialog.set_directory(dir);
+        }
+
+        let paths = dialog.pick_files().unwrap_or_default();
+        if let Some(path) = paths.first() {
+            self.settings.last_upload_dir = path.parent().map(|p| p.to_path_buf());
+        }
+
+        paths
+    }
+
+    /// Pick a playlist and queue its files for upload, skipping any which
+    /// don't exist.
+    fn import_playlist(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Import Playlist")
+            .add_filter("Playlist", &["m3u", "m3u8", "json"]);
+
+        if let Some(dir) = &self.settings.last_upload_dir {
+            dialog = dialog.set_directory(dir);
+        }
+
+        let Some(path) = dialog.pick_file() else {
+            return;
+        };
+
+        let entries = match read_playlist(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.md_state.write().unwrap().report_error(format!("Could not read {}: {e}", path.display()));
+                return;
+            }
+        };
+
+        let (found, missing): (Vec<_>, Vec<_>) = entries.into_iter().partition(|(p, _)| p.is_file());
+        self.playlist_missing = missing.into_iter().map(|(p, _)| p).collect();
+
+        let uploads: Vec<UploadDialog> = found.into_iter()
+            .map(|(path, title)| {
+                let mut dialog = UploadDialog::new(path);
+                if let Some(title) = title {
+                    dialog.title = title;
+                }
+                dialog
+            })
+            .collect();
+
+        self.queue_uploads(uploads);
+    }
+
+    /// Ask where to save the disc's track listing, then write it there.
+    fn export_listing(&self) {
+        let file_name = match &self.md_state.read().unwrap().disc_contents {
+            Some(dc) if !dc.title().is_empty() => format!("{}.csv", dc.title()),
+            _ => "listing.csv".to_string(),
+        };
+
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export Listing")
+            .add_filter("CSV", &["csv"])
+            .add_filter("JSON", &["json"])
+            .set_file_name(file_name)
+            .save_file()
+        else {
+            return;
+        };
+
+        let mut state = self.md_state.write().unwrap();
+        let result = match &state.disc_contents {
+            Some(dc) => write_listing(dc, &path),
+            None => return,
+        };
+
+        if let Err(e) = result {
+            state.report_error(format!("Could not export the listing: {e}"));
+        }
+    }
+}
+
+/// Write a disc's track listing to a file, as JSON if the file name ends in
+/// `.json` and CSV otherwise.
+fn write_listing(disc: &Disc, path: &Path) -> Result<()> {
+    let json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    let contents = if json {
+        let tracks: Vec<serde_json::Value> = disc.tracks().iter()
+            .map(|t| serde_json::json!({
+                "index": t.index() + 1,
+                "title": t.title(),
+                "full_width_title": t.full_width_title(),
+                "encoding": t.encoding().to_string().to_ascii_uppercase(),
+                "duration": t.duration().as_duration().as_secs_f64(),
+            }))
+            .collect();
+
+        serde_json::to_string_pretty(&serde_json::json!({
+            "title": disc.title(),
+            "tracks": tracks,
+        }))?
+    } else {
+        let mut csv = String::from("index,title,full_width_title,encoding,duration\n");
+        for t in disc.tracks().iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                t.index() + 1,
+                csv_field(t.title()),
+                csv_field(t.full_width_title()),
+                t.encoding().to_string().to_ascii_uppercase(),
+                pretty_duration(t.duration().as_duration(), DurationFormat::Full),
+            ));
+        }
+        csv
+    };
+
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Read the entries of an M3U playlist or a JSON list, each a path and an
+/// optional title. Relative paths are resolved against the playlist's folder.
+///
+/// JSON playlists are a list of either paths or `{"path": ..., "title": ...}`
+/// objects.
+fn read_playlist(path: &Path) -> Result<Vec<(PathBuf, Option<String>)>> {
+    let contents = std::fs::read_to_string(path)?;
+    let contents = contents.trim_start_matches('\u{feff}');
+    let json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    let entries = if json {
+        let list: Vec<serde_json::Value> = serde_json::from_str(contents)?;
+        list.into_iter()
+            .map(|entry| match entry {
+                serde_json::Value::String(path) => Ok((PathBuf::from(path), None)),
+                serde_json::Value::Object(entry) => {
+                    let path = entry.get("path")
+                        .and_then(|p| p.as_str())
+                        .ok_or_else(|| anyhow!("Playlist entry is missing a path"))?;
+                    let title = entry.get("title").and_then(|t| t.as_str()).map(str::to_string);
+                    Ok((PathBuf::from(path), title))
+                }
+                _ => Err(anyhow!("Playlist entries must be paths or objects")),
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        let mut entries = Vec::new();
+        // The title from an `#EXTINF` line applies to the path after it
+        let mut title = None;
+        for line in contents.lines().map(str::trim) {
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                title = info.split_once(',')
+                    .map(|(_, t)| t.trim().to_string())
+                    .filter(|t| !t.is_empty());
+            } else if !line.is_empty() && !line.starts_with('#') {
+                entries.push((PathBuf::from(line), title.take()));
+            }
+        }
+        entries
+    };
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok(entries.into_iter().map(|(p, title)| (dir.join(p), title)).collect())
+}
+
+/// Quote a CSV field if it contains anything that would break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Controls for the settings which change how the device is talked to
+fn device_settings(ui: &mut egui::Ui, settings: &mut DeviceSettings) {
+    let mut poll_interval = settings.poll_interval.as_millis() as u64;
+    let response = ui.add(
+        egui::Slider::new(&mut poll_interval, 250..=5000)
+            .logarithmic(true)
+            .suffix(" ms")
+            .text("Status poll interval")
+    ).on_hover_text("Slower polling is easier on older devices, but the display updates less often");
+    if response.changed() {
+        settings.poll_interval = Duration::from_millis(poll_interval);
+    }
+
+    ui.add(egui::Slider::new(&mut settings.retry_attempts, 0..=5).text("Retries"))
+        .on_hover_text("How many times to retry a status check or playback command the device fails");
+
+    ui.checkbox(&mut settings.auto_reconnect, "Reconnect automatically")
+        .on_hover_text("Keep trying to connect again if the device is lost");
+    ui.add_enabled_ui(settings.auto_reconnect, |ui| {
+        let mut interval = settings.reconnect_interval.as_secs_f32();
+        if ui.add(egui::Slider::new(&mut interval, 1.0..=30.0).suffix(" s").text("Retry interval")).changed() {
+            settings.reconnect_interval = Duration::from_secs_f32(interval);
+        }
+        ui.add(egui::Slider::new(&mut settings.reconnect_attempts, 1..=100).text("Attempts"));
+    });
+
+    ui.collapsing("Advanced", |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Upload chunk size");
+            egui::ComboBox::from_id_salt("upload_chunk_size")
+                .selected_text(format!("{} bytes", settings.chunk_size()))
+                .show_ui(ui, |ui| {
+                    for size in CHUNK_SIZES {
+                        let label = if size == DEFAULT_CHUNK_SIZE {
+                            format!("{size} bytes (default)")
+                        } else {
+                            format!("{size} bytes")
+                        };
+                        ui.selectable_value(&mut settings.upload_chunk_size, size, label);
+                    }
+                });
+        }).response.on_hover_text(
+            "How much audio is sent at a time. Some devices upload faster with larger chunks, \
+            but others fail partway through, so change this only if uploads are slow or unreliable"
+        );
+    });
+}
+
+/// A name for a USB device to show in the device picker
+async fn device_label(device: &DeviceInfo) -> String {
+    match device.product_string().await {
+        Some(name) => name,
+        None => format!("{:04x}:{:04x}", device.vendor_id().await, device.product_id().await),
+    }
+}
+
+const PLAY_PAUSE_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Space);
+const STOP_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::S);
+const PREVIOUS_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::ArrowLeft);
+const NEXT_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::ArrowRight);
+const UNDO_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+const UPLOAD_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::O);
+const COMMAND_LOG_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Backtick);
+const QUIT_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Q);
+
+/// Every keyboard shortcut, with what it does, for the help window
+const SHORTCUTS: &[(&str, egui::KeyboardShortcut)] = &[
+    ("Play/Pause", PLAY_PAUSE_SHORTCUT),
+    ("Stop", STOP_SHORTCUT),
+    ("Previous track", PREVIOUS_SHORTCUT),
+    ("Next track", NEXT_SHORTCUT),
+    ("Undo", UNDO_SHORTCUT),
+    ("Upload", UPLOAD_SHORTCUT),
+    ("Command Log", COMMAND_LOG_SHORTCUT),
+    ("Quit", QUIT_SHORTCUT),
+];
+
+/// How long to wait after the last skip button press before skipping, so a
+/// burst of presses becomes a single jump
+const SKIP_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The title given to groups when they're created
+const NEW_GROUP_TITLE: &str = "New Group";
+
+/// What the user has to type to confirm erasing a disc
+const ERASE_CONFIRMATION_TEXT: &str = "ERASE";
+
+/// Shown on controls which are disabled because the disc can't be edited
+const WRITE_PROTECTED_HINT: &str = "The disc is write-protected, slide the tab on the disc to edit it";
+
+/// Shown on controls which are disabled because there's no device to use
+const NOT_CONNECTED_HINT: &str = "Connect a device first";
+
+/// Shown on controls which are disabled until the current upload finishes
+const TRANSFER_IN_PROGRESS_HINT: &str = "Transfer in progress";
+
+/// File extensions which can be uploaded to a device
+const UPLOAD_EXTENSIONS: &[&str] = &["wav", "raw", "aea"];
+
+/// Whether a path is a file with one of the [`UPLOAD_EXTENSIONS`]
+fn is_uploadable(path: &std::path::Path) -> bool {
+    path.is_file() && path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| UPLOAD_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// A file in the upload queue, and the format it will be uploaded in
+struct QueuedUpload {
+    details: UploadDialog,
+    format: UploadFormat,
+}
+
+/// A file in the upload queue as it's saved between sessions
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedUpload {
+    path: PathBuf,
+    title: String,
+    full_width_title: String,
+    format: UploadFormat,
+    level: Level,
+}
+
+impl From<&QueuedUpload> for SavedUpload {
+    fn from(queued: &QueuedUpload) -> Self {
+        Self {
+            path: queued.details.path.clone(),
+            title: queued.details.title.clone(),
+            full_width_title: queued.details.full_width_title.clone(),
+            format: queued.format,
+            level: queued.details.level,
+        }
+    }
+}
+
+impl SavedUpload {
+    fn restore(self) -> QueuedUpload {
+        let mut details = UploadDialog::new(self.path);
+        details.title = self.title;
+        details.full_width_title = self.full_width_title;
+        details.level = self.level;
+
+        QueuedUpload { details, format: self.format }
+    }
+}
+
+/// The details of a track which is about to be uploaded, as edited by the user
+struct UploadDialog {
+    path: PathBuf,
+    title: String,
+    full_width_title: String,
+    /// The format of the file if it's a WAV, or why it couldn't be read
+    wav_spec: Option<Result<hound::WavSpec, String>>,
+    /// How long the audio is, if it's a WAV
+    wav_length: Option<Duration>,
+    /// The size of the file, used to work out how long raw data is
+    file_size: Option<u64>,
+    level: Level,
+}
+
+impl UploadDialog {
+    fn new(path: PathBuf) -> Self {
+        let is_wav = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+        let mut wav_length = None;
+        let wav_spec = is_wav.then(|| {
+            hound::WavReader::open(&path)
+                .map(|r| {
+                    let spec = r.spec();
+                    wav_length = Some(Duration::from_secs_f64(r.duration() as f64 / spec.sample_rate as f64));
+                    spec
+                })
+                .map_err(|e| e.to_string())
+        });
+
+        Self {
+            title: file_stem(&path),
+            full_width_title: String::new(),
+            wav_spec,
+            wav_length,
+            file_size: std::fs::metadata(&path).ok().map(|m| m.len()),
+            level: Level::default(),
+            path,
+        }
+    }
+
+    /// How much disc time the file will take up in the given format, if it
+    /// can be worked out. Raw data is assumed to already be in that format.
+    fn length(&self, format: UploadFormat) -> Option<Duration> {
+        match &self.wav_spec {
+            Some(Ok(_)) => self.wav_length,
+            Some(Err(_)) => None,
+            None => self.file_size.map(|size| Duration::from_secs_f64(size as f64 / format.bytes_per_second())),
+        }
+    }
+
+    /// Whether the file is decoded here, so its volume can be changed
+    fn is_adjustable(&self) -> bool {
+        matches!(self.wav_spec, Some(Ok(_)))
+    }
+
+    /// Why the file can't be uploaded in the given format, if it can't
+    fn problem(&self, format: UploadFormat) -> Option<String> {
+        match &self.wav_spec {
+            Some(Ok(spec)) => wav_problem(spec, format),
+            Some(Err(e)) => Some(format!("The file could not be read: {e}")),
+            None => None,
+        }
+    }
+
+    fn to_job(&self, format: UploadFormat) -> UploadJob {
+        let title = if self.title.trim().is_empty() {
+            file_stem(&self.path)
+        } else {
+            self.title.clone()
+        };
+
+        let full_width_title = if self.full_width_title.trim().is_empty() {
+            None
+        } else {
+            Some(self.full_width_title.clone())
+        };
+
+        UploadJob {
+            path: self.path.clone(),
+            title,
+            full_width_title,
+            format,
+            level: self.level,
+        }
+    }
+}
+
+/// List the titles a batch rename will change, with each one's old and new
+/// title. Only the titles which change are shown, half-width first.
+fn rename_preview(ui: &mut egui::Ui, titles: &[TrackTitles], renames: &[(usize, TrackTitles)]) {
+    egui::ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+        egui::Grid::new("rename_preview").num_columns(3).striped(true).show(ui, |ui| {
+            for (index, renamed) in renames {
+                let current = titles.get(*index).cloned().unwrap_or_default();
+                let mut before = Vec::new();
+                let mut after = Vec::new();
+                if renamed.title != current.title {
+                    before.push(current.title.as_str());
+                    after.push(renamed.title.as_str());
+                }
+                if renamed.full_width_title != current.full_width_title {
+                    before.push(current.full_width_title.as_str());
+                    after.push(renamed.full_width_title.as_str());
+                }
+
+                ui.weak(format!("{}", index + 1));
+                ui.label(egui::RichText::new(before.join(" / ")).strikethrough().weak());
+                ui.horizontal(|ui| {
+                    ui.label(format!("→ {}", after.join(" / ")));
+                    if let Some(hint) = after.iter().find_map(|t| title_change_hint(t)) {
+                        ui.colored_label(ui.visuals().warn_fg_color, "⚠").on_hover_text(hint);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Both of a track's titles, as they're stored
+fn track_titles(track: &Track) -> TrackTitles {
+    TrackTitles {
+        title: track.title().clone(),
+        full_width_title: track.full_width_title().clone(),
+    }
+}
+
+/// The name of a file without its extension, used as a default track title
+fn file_stem(path: &std::path::Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The recording modes a track can be uploaded in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum UploadFormat {
+    SP,
+    #[default]
+    LP2,
+    LP4,
+}
+
+impl UploadFormat {
+    const ALL: [Self; 3] = [Self::SP, Self::LP2, Self::LP4];
+
+    fn wire_format(self) -> WireFormat {
+        match self {
+            Self::SP => WireFormat::Pcm,
+            Self::LP2 => WireFormat::LP2,
+            Self::LP4 => WireFormat::LP4,
+        }
+    }
+
+    /// How many bytes of audio are sent to the device for each second of the track
+    fn bytes_per_second(self) -> f64 {
+        match self {
+            // SP is sent as 16 bit stereo PCM at 44.1kHz and encoded by the device
+            Self::SP => 176_400.0,
+            // ATRAC3 frames of 512 samples are 192 bytes in LP2 and 96 in LP4
+            Self::LP2 => 16_537.5,
+            Self::LP4 => 8_268.75,
+        }
+    }
+
+    /// How many minutes of audio fit into one minute of SP disc time
+    fn time_multiplier(self) -> u32 {
+        match self {
+            Self::SP => 1,
+            Self::LP2 => 2,
+            Self::LP4 => 4,
+        }
+    }
+
+    /// The format a track on the disc was recorded in
+    fn of_track(track: &Track) -> Self {
+        match track.encoding().to_string().to_ascii_uppercase().as_str() {
+            "LP2" => Self::LP2,
+            "LP4" => Self::LP4,
+            _ => Self::SP,
+        }
+    }
+
+    /// The color used to show tracks recorded in this format
+    fn color(self) -> egui::Color32 {
+        match self {
+            Self::SP => egui::Color32::from_rgb(70, 130, 200),
+            Self::LP2 => egui::Color32::from_rgb(80, 170, 100),
+            Self::LP4 => egui::Color32::from_rgb(220, 140, 60),
+        }
+    }
+
+    /// The bitrate tracks in this format are stored at on the disc, in kbps
+    fn bitrate(self) -> u32 {
+        match self {
+            Self::SP => 292,
+            Self::LP2 => 132,
+            Self::LP4 => 66,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::SP => "Best quality, uses 1 minute of disc time per minute of audio",
+            Self::LP2 => "Good quality, uses 30 seconds of disc time per minute of audio",
+            Self::LP4 => "Lowest quality, uses 15 seconds of disc time per minute of audio",
+        }
+    }
+}
+
+impl std::fmt::Display for UploadFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SP => write!(f, "SP"),
+            Self::LP2 => write!(f, "LP2"),
+            Self::LP4 => write!(f, "LP4"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackListingTable {
+    /// The track title currently being edited
+    title_edit: Option<TitleEdit>,
+    /// The track currently being dragged to a new position
+    dragging: Option<usize>,
+    /// The position the dragged track would be inserted before
+    drop_target: Option<usize>,
+    /// Tracks the user asked to delete, waiting to be confirmed
+    pending_delete: Option<Vec<usize>>,
+    /// Tracks the user asked to number, in order
+    pending_numbering: Option<Vec<usize>>,
+    /// The tracks currently selected in the table
+    selected: HashSet<usize>,
+    /// The track a shift-click selection extends from
+    selection_anchor: Option<usize>,
+    /// Only show tracks whose titles contain this
+    filter: String,
+    sort_column: SortColumn,
+    sort_descending: bool,
+    /// The group title currently being edited
+    group_edit: Option<TitleEdit>,
+    /// Group sections which are folded away, with `None` for ungrouped tracks
+    collapsed_groups: HashSet<Option<usize>>,
+    /// Prefer full-width titles over half-width ones where a track has both
+    show_full_width: bool,
+    /// Disable editing, for write-protected discs
+    read_only: bool,
+    /// Tracks with an edit in progress
+    busy: HashSet<usize>,
+    duration_format: DurationFormat,
+    density: TableDensity,
+    scroll_offset: f32,
+    /// The frame the table was last drawn in
+    last_shown_pass: u64,
+}
+
+/// A row in the track listing
+#[derive(Clone, Copy)]
+enum ListingRow {
+    /// The header of a group's section, or the ungrouped tracks' if `None`
+    Group(Option<usize>),
+    Track(usize),
+}
+
+/// The column the track listing is sorted by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    #[default]
+    Index,
+    Title,
+    Encoding,
+    Duration,
+}
+
+/// An in-progress edit of a track or group title
+struct TitleEdit {
+    index: usize,
+    title: String,
+    /// Whether the text field should grab focus on the next frame
+    request_focus: bool,
+}
+
+impl TrackListingTable {
+    /// A column header which sorts by that column when clicked, or reverses
+    /// the order if it's already sorted by it.
+    fn sort_header(&mut self, ui: &mut egui::Ui, label: &str, column: SortColumn) {
+        let text = if self.sort_column != column {
+            label.to_string()
+        } else if self.sort_descending {
+            format!("{label} ⏷")
+        } else {
+            format!("{label} ⏶")
+        };
+
+        let response = ui.add(egui::Label::new(egui::RichText::new(text).strong()).sense(egui::Sense::click()));
+        if response.clicked() {
+            if self.sort_column == column {
+                self.sort_descending = !self.sort_descending;
+            } else {
+                self.sort_column = column;
+                self.sort_descending = false;
+            }
+        }
+    }
+
+    /// Update the selection for a click on a row, extending it with shift
+    /// and toggling rows with ctrl/cmd.
+    fn click_row(&mut self, index: usize, modifiers: egui::Modifiers) {
+        if modifiers.shift && let Some(anchor) = self.selection_anchor {
+            if !modifiers.command {
+                self.selected.clear();
+            }
+            self.selected.extend(anchor.min(index)..=anchor.max(index));
+            return;
+        }
+
+        if modifiers.command {
+            if !self.selected.remove(&index) {
+                self.selected.insert(index);
+            }
+        } else {
+            self.selected.clear();
+            self.selected.insert(index);
+        }
+        self.selection_anchor = Some(index);
+    }
+
+    /// The listing split into a section for each group, leaving out the
+    /// tracks of collapsed sections.
+    fn grouped_rows(&self, groups: &[TrackGroup], track_count: usize) -> Vec<ListingRow> {
+        let ungrouped: Vec<usize> = (0..track_count)
+            .filter(|&i| !groups.iter().any(|g| g.contains(i)))
+            .collect();
+
+        let sections = (!ungrouped.is_empty())
+            .then_some((None, ungrouped))
+            .into_iter()
+            .chain(groups.iter().enumerate().map(|(g, group)| {
+                (Some(g), (group.first..=group.last).filter(|&i| i < track_count).collect())
+            }));
+
+        let mut rows = Vec::new();
+        for (group, tracks) in sections {
+            rows.push(ListingRow::Group(group));
+            if !self.collapsed_groups.contains(&group) {
+                rows.extend(tracks.into_iter().map(ListingRow::Track));
+            }
+        }
+
+        rows
+    }
+
+    /// The header row of a group's section, which folds the section away when
+    /// clicked.
+    fn group_header(
+        &mut self,
+        row: &mut egui_extras::TableRow,
+        group: Option<usize>,
+        groups: &[TrackGroup],
+        disc: &Disc,
+        channel: Option<&async_channel::Sender<PlayerCommand>>,
+    ) {
+        let in_section = |i: usize| match group {
+            Some(g) => groups[g].contains(i),
+            None => !groups.iter().any(|g| g.contains(i)),
+        };
+        let (count, duration) = disc.tracks().iter()
+            .enumerate()
+            .filter(|(i, _)| in_section(*i))
+            .fold((0, Duration::ZERO), |(count, total), (_, t)| (count + 1, total + t.duration().as_duration()));
+
+        let collapsed = self.collapsed_groups.contains(&group);
+        row.col(|ui| {
+            ui.label(if collapsed { "⏵" } else { "⏷" });
+        });
+        row.col(|ui| {
+            let Some(edit) = self.group_edit.as_mut().filter(|e| Some(e.index) == group) else {
+                ui.strong(group.map_or("Ungrouped", |g| groups[g].title.as_str()));
+                return;
+            };
+
+            let mut response = ui.add(egui::TextEdit::singleline(&mut edit.title).desired_width(f32::INFINITY));
+            if edit.request_focus {
+                response.request_focus();
+                edit.request_focus = false;
+            }
+            if let Some(hint) = title_change_hint(&edit.title) {
+                response = response.on_hover_text(hint);
+            }
+
+            if response.lost_focus() {
+                if ui.input(|i| i.key_pressed(egui::Key::Enter))
+                    && let Some(ch) = channel
+                {
+                    let _ = ch.send_blocking(PlayerCommand::RenameGroup { group: edit.index, title: edit.title.clone() });
+                }
+                self.group_edit = None;
+            }
+        });
+        row.col(|ui| {
+            ui.weak(if count == 1 { "1 track".to_string() } else { format!("{count} tracks") });
+        });
+        row.col(|ui| {
+            ui.weak(pretty_duration(duration, self.duration_format));
+        });
+        row.col(|_| ());
+
+        if row.response().clicked() && self.group_edit.is_none() {
+            if collapsed {
+                self.collapsed_groups.remove(&group);
+            } else {
+                self.collapsed_groups.insert(group);
+            }
+        }
+
+        if let Some(g) = group && let Some(ch) = channel {
+            row.response().context_menu(|ui| {
+                if ui.add_enabled(!self.read_only, egui::Button::new("Rename Group").small())
+                    .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                    .clicked()
+                {
+                    ui.close_menu();
+                    self.group_edit = Some(TitleEdit {
+                        index: g,
+                        title: groups[g].title.clone(),
+                        request_focus: true,
+                    });
+                }
+                if ui.add_enabled(!self.read_only, egui::Button::new("Ungroup").small())
+                    .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                    .clicked()
+                {
+                    ui.close_menu();
+                    let _ = ch.send_blocking(PlayerCommand::Ungroup(g));
+                }
+            });
+        }
+    }
+
+    fn table(
+        &mut self,
+        ui: &mut egui::Ui,
+        disc: &Disc,
+        groups: &[TrackGroup],
+        playing: Option<usize>,
+        next: Option<usize>,
+        channel: &mut Option<async_channel::Sender<PlayerCommand>>,
+    ) {
+        let row_height = self.density.row_height(
+            egui::TextStyle::Body.resolve(ui.style()).size,
+            ui.spacing().interact_size.y,
+        );
+
+        let painter = ui.painter().clone();
+        let indicator_color = ui.visuals().selection.bg_fill;
+        self.drop_target = None;
+
+        // Keep the selection after tracks are removed from the end, rather than losing it
+        let track_count = disc.track_count() as usize;
+        let had_selection = !self.selected.is_empty();
+        self.selected.retain(|&i| i < track_count);
+        if had_selection && self.selected.is_empty() && track_count > 0 {
+            self.selected.insert(track_count - 1);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Search tracks"));
+            if !self.filter.is_empty() && ui.small_button("✖").on_hover_text("Clear search").clicked() {
+                self.filter.clear();
+            }
+            ui.checkbox(&mut self.show_full_width, "Full-width titles")
+                .on_hover_text("Show the full-width title of tracks which have one");
+        });
+
+        // The disc indices of the tracks matching the search, in display order
+        let filter = self.filter.to_lowercase();
+        let mut rows: Vec<usize> = disc.tracks().iter()
+            .enumerate()
+            .filter(|(_, t)| {
+                filter.is_empty()
+                    || t.title().to_lowercase().contains(&filter)
+                    || t.full_width_title().to_lowercase().contains(&filter)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let tracks = disc.tracks();
+        match self.sort_column {
+            SortColumn::Index => (),
+            SortColumn::Title => rows.sort_by_cached_key(|&i| tracks[i].title().to_lowercase()),
+            SortColumn::Encoding => rows.sort_by_cached_key(|&i| tracks[i].encoding().to_string()),
+            SortColumn::Duration => rows.sort_by_key(|&i| tracks[i].duration().as_duration()),
+        }
+        if self.sort_descending {
+            rows.reverse();
+        }
+
+        // Dropping a track between rows only makes sense in disc order
+        let reorderable = self.sort_column == SortColumn::Index && !self.sort_descending;
+
+        // Sections only make sense when the whole disc is shown in order
+        let rows: Vec<ListingRow> = if reorderable && filter.is_empty() && !groups.is_empty() {
+            self.grouped_rows(groups, track_count)
+        } else {
+            rows.into_iter().map(ListingRow::Track).collect()
+        };
+
+        let available_height = ui.available_height();
+        let mut table = TableBuilder::new(ui)
+            .resizable(false)
+            .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto())
+            .column(
+                Column::remainder()
+                    .at_least(40.0)
+                    .clip(true),
+            )
+            .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::auto())
+            .min_scrolled_height(0.0)
+            .max_scroll_height(available_height);
+
+        table = table.sense(egui::Sense::click_and_drag());
+
+        // The table isn't shown while the disc is being read, so put the
+        // scroll position back when it comes back
+        let pass = ui.ctx().cumulative_pass_nr();
+        if pass > self.last_shown_pass + 1 {
+            table = table.vertical_scroll_offset(self.scroll_offset);
+        }
+        self.last_shown_pass = pass;
+
+        let output = table.header(20.0, |mut header| {
+            header.col(|ui| {
+                self.sort_header(ui, "#", SortColumn::Index);
+            });
+            header.col(|ui| {
+                self.sort_header(ui, "Title", SortColumn::Title);
+            });
+            header.col(|ui| {
+                self.sort_header(ui, "Format", SortColumn::Encoding);
+            });
+            header.col(|ui| {
+                self.sort_header(ui, "Duration", SortColumn::Duration);
+            });
+            header.col(|ui| {
+                ui.strong("");
+            });
+        })
+        .body(|body| {
+            body.rows(row_height, rows.len(), |mut row| {
+                // Everything below works with the track's real position on the disc
+                let index = match rows[row.index()] {
+                    ListingRow::Track(index) => index,
+                    ListingRow::Group(group) => {
+                        self.group_header(&mut row, group, groups, disc, channel.as_ref());
+                        return;
+                    }
+                };
+                let row_track = &disc.tracks()[index];
+
+                let title = track_title(row_track, self.show_full_width);
+
+                let is_playing = playing.is_some_and(|p| p == index);
+                let is_busy = self.busy.contains(&index);
+                if self.selected.contains(&index) {
+                    row.set_selected(true);
+                }
+
+                let is_next = next == Some(index);
+
+                row.col(|ui| {
+                    let number = egui::RichText::new((row_track.index() + 1).to_string());
+                    ui.label(if is_next { number.strong() } else { number });
+                });
+                row.col(|ui| {
+                    let Some(edit) = self.title_edit.as_mut().filter(|e| e.index == index) else {
+                        // The playing track stands out without looking selected
+                        let text = if is_playing {
+                            egui::RichText::new(title.as_str()).strong().color(ui.visuals().selection.stroke.color)
+                        } else {
+                            egui::RichText::new(title.as_str())
+                        };
+                        ui.add(egui::Label::new(text).truncate());
+                        return;
+                    };
+
+                    let mut response = ui.add(
+                        egui::TextEdit::singleline(&mut edit.title).desired_width(f32::INFINITY).hint_text("No Title")
+                    );
+                    if edit.request_focus {
+                        response.request_focus();
+                        edit.request_focus = false;
+                    }
+                    if let Some(hint) = title_change_hint(&edit.title) {
+                        response = response.on_hover_text(hint);
+                    } else if half_width_title(&edit.title) != edit.title {
+                        response = response.on_hover_text(
+                            "Characters the device can't display will only be kept in the full-width title"
+                        );
+                    }
+
+                    if response.lost_focus() {
+                        if ui.input(|i| i.key_pressed(egui::Key::Enter))
+                            && let Some(ch) = channel.as_ref()
+                        {
+                            let _ = ch.send_blocking(PlayerCommand::RenameTrack { index, title: edit.title.clone() });
+                        }
+                        self.title_edit = None;
+                    }
+                });
+                row.col(|ui| {
+                    let format = UploadFormat::of_track(row_track);
+                    ui.label(egui::RichText::new(format.to_string()).color(format.color()))
+                        .on_hover_text(format!("{} kbps\n{}", format.bitrate(), format.description()));
+                });
+                row.col(|ui| {
+                    ui.label(pretty_duration(row_track.duration().as_duration(), self.duration_format));
+                });
+                row.col(|ui| {
+                    if is_busy {
+                        ui.spinner();
+                    } else if is_playing {
+                        ui.label("▶").on_hover_text("Playing");
+                    } else if is_next {
+                        ui.weak("⏭").on_hover_text("Next track");
+                    } else {
+                        ui.label(" ");
+                    }
+                });
+
+                let response = row.response();
+                if response.clicked() {
+                    let modifiers = response.ctx.input(|i| i.modifiers);
+                    self.click_row(index, modifiers);
+                } else if response.secondary_clicked() && !self.selected.contains(&index) {
+                    self.click_row(index, egui::Modifiers::NONE);
+                }
+
+                if reorderable && !self.read_only && !is_busy && response.drag_started() {
+                    self.dragging = Some(index);
+                }
+
+                if self.dragging.is_some() && response.contains_pointer()
+                    && let Some(pos) = response.ctx.pointer_latest_pos()
+                {
+                    let rect = response.rect;
+                    let before = pos.y < rect.center().y;
+                    self.drop_target = Some(if before { index } else { index + 1 });
+
+                    let y = if before { rect.top() } else { rect.bottom() };
+                    painter.hline(rect.x_range(), y, egui::Stroke::new(2.0, indicator_color));
+                }
+
+                if let Some(ch) = channel && !is_busy {
+                    if row.response().double_clicked() {
+                        let _ = ch.send_blocking(PlayerCommand::GoToTrack(index));
+                    }
+
+                    row.response().context_menu(|ui| {
+                        if ui.small_button("Play From Here").clicked() {
+                            ui.close_menu();
+                            let _ = ch.send_blocking(PlayerCommand::GoToTrack(index));
+                        }
+
+                        ui.separator();
+
+                        if ui.add_enabled(!self.read_only, egui::Button::new("Rename").small())
+                            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                            .clicked()
+                        {
+                            ui.close_menu();
+                            // Start from nothing rather than the placeholder for untitled tracks
+                            let untitled = row_track.title().is_empty() && row_track.full_width_title().is_empty();
+                            self.title_edit = Some(TitleEdit {
+                                index,
+                                title: if untitled { String::new() } else { title.clone() },
+                                request_focus: true,
+                            });
+                        }
+                        if self.selected.len() > 1
+                            && ui.add_enabled(
+                                !self.read_only,
+                                egui::Button::new(format!("Number {} Selected…", self.selected.len())).small(),
+                            ).clicked()
+                        {
+                            ui.close_menu();
+                            let mut tracks: Vec<usize> = self.selected.iter().copied().collect();
+                            tracks.sort_unstable();
+                            self.pending_numbering = Some(tracks);
+                        }
+                        if ui.small_button("Save to computer…").clicked() {
+                            ui.close_menu();
+                            let dest = rfd::FileDialog::new()
+                                .set_title("Save Track")
+                                .set_file_name(format!("{title}.raw"))
+                                .save_file();
+
+                            if let Some(dest) = dest {
+                                let _ = ch.send_blocking(PlayerCommand::DownloadTrack { index, dest });
+                            }
+                        }
+                        if ui.add_enabled(!self.read_only, egui::Button::new("Delete").small())
+                            .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                            .clicked()
+                        {
+                            ui.close_menu();
+                            self.pending_delete = Some(vec![index]);
+                        }
+                        if self.selected.len() > 1
+                            && ui.add_enabled(
+                                !self.read_only,
+                                egui::Button::new(format!("Delete {} Selected", self.selected.len())).small(),
+                            ).clicked()
+                        {
+                            ui.close_menu();
+                            self.pending_delete = Some(self.selected.iter().copied().collect());
+                        }
+                        if index + 1 < track_count
+                            && ui.add_enabled(!self.read_only, egui::Button::new("Erase From Here to End").small())
+                                .on_hover_text(format!("Erase tracks {} to {track_count}", index + 1))
+                                .on_disabled_hover_text(WRITE_PROTECTED_HINT)
+                                .clicked()
+                        {
+                            ui.close_menu();
+                            self.pending_delete = Some((index..track_count).collect());
+                        }
+
+                        ui.separator();
+
+                        // Groups are ranges, so only an unbroken run of ungrouped tracks can become one
+                        let first = self.selected.iter().copied().min().unwrap_or(index);
+                        let last = self.selected.iter().copied().max().unwrap_or(index);
+                        let groupable = last - first + 1 == self.selected.len().max(1)
+                            && !groups.iter().any(|g| g.overlaps(first, last));
+
+                        let response = ui.add_enabled(groupable && !self.read_only, egui::Button::new("Group Selected").small())
+                            .on_disabled_hover_text(if self.read_only {
+                                WRITE_PROTECTED_HINT
+                            } else {
+                                "Only consecutive tracks which aren't already in a group can be grouped"
+                            });
+                        if response.clicked() {
+                            ui.close_menu();
+                            let _ = ch.send_blocking(PlayerCommand::CreateGroup { first, last, title: NEW_GROUP_TITLE.to_string() });
+                            self.selected.clear();
+                        }
+
+                        // `Some(None)` for a new group
+                        let mut move_to = None;
+                        ui.add_enabled_ui(!self.read_only, |ui| {
+                            ui.menu_button("Move to Group", |ui| {
+                                let current = groups.iter().position(|g| g.contains(index));
+                                for (g, group) in groups.iter().enumerate() {
+                                    if Some(g) != current && ui.small_button(&group.title).clicked() {
+                                        move_to = Some(Some(g));
+                                    }
+                                }
+                                if !groups.is_empty() {
+                                    ui.separator();
+                                }
+                                if ui.small_button("New Group").clicked() {
+                                    move_to = Some(None);
+                                }
+                            });
+                        });
+
+                        if let Some(group) = move_to {
+                            ui.close_menu();
+                            let _ = ch.send_blocking(PlayerCommand::MoveTrackToGroup { track: index, group });
+
+                            // Follow the track to its new place, in case that's out of view
+                            let (to, _) = move_to_group(groups, track_count, index, group, NEW_GROUP_TITLE);
+                            self.selected.clear();
+                            self.selected.insert(to);
+                            if group.is_some() {
+                                self.collapsed_groups.remove(&group);
+                            }
+                        }
+                    });
+                }
+            });
+        });
+        self.scroll_offset = output.state.offset.y;
+
+        // Rows scrolled out of view don't get a response, so finish the drag
+        // here rather than relying on the dragged row's `drag_stopped`
+        if self.dragging.is_some() && ui.input(|i| i.pointer.any_released()) {
+            let from = self.dragging.take().unwrap();
+            if let Some(target) = self.drop_target.take() {
+                // Removing the track first shifts everything after it up one
+                let to = if target > from { target - 1 } else { target };
+
+                if from != to && let Some(ch) = channel {
+                    let _ = ch.send_blocking(PlayerCommand::MoveTrack { from, to });
+                    self.selected.clear();
+                    self.selected.insert(to);
+                }
+            }
+        }
+    }
+}
+
+
+/// The title to show for a track, falling back to the full-width title when
+/// there's no half-width one.
+fn track_title(track: &Track, prefer_full_width: bool) -> String {
+    let half_width = track.title();
+    let full_width = track.full_width_title();
+
+    if !full_width.is_empty() && (prefer_full_width || half_width.is_empty()) {
+        full_width.clone()
+    } else if !half_width.is_empty() {
+        half_width.clone()
+    } else {
+        "No Title".to_string()
+    }
+}
+
+/// A short label for a device state and a hint about what the user can do
+/// next, shared by the status bar and the track listing so they agree.
+///
+/// Devices report no state at all while they're switching between two, which
+/// is shown as such rather than as any particular state.
+fn describe_status(state: Option<OS>) -> (&'static str, &'static str) {
+    match state {
+        Some(OS::Ready) => ("✅ Ready", "Stopped, ready to play or edit the disc"),
+        Some(OS::Playing) => ("▶ Playing", "Playing a track"),
+        Some(OS::Paused) => ("⏸ Paused", "Press play to carry on"),
+        Some(OS::FastForward) => ("⏩ Fast forward", "Searching forwards through the track"),
+        Some(OS::Rewind) => ("⏪ Rewind", "Searching backwards through the track"),
+        Some(OS::ReadingTOC) => ("🔄 Reading disc", "Reading the disc's contents, this takes a few seconds"),
+        Some(OS::NoDisc) => ("No Disc", "Insert a disc to see its tracks"),
+        Some(OS::DiscBlank) => ("Blank Disc", "There are no tracks on this disc yet, upload some to fill it"),
+        Some(OS::ReadyForTransfer) => ("📥 Transferring", "Set up to receive audio, don't remove the disc"),
+        None => ("⏳ Changing state", "The device is between states, this should settle in a moment"),
+    }
+}
+
+fn pretty_duration(duration: Duration, format: DurationFormat) -> String {
+    let secs = duration.as_secs();
+    if format == DurationFormat::Compact && secs < 3600 {
+        return format!("{:02}:{:02}", secs / 60, secs % 60);
+    }
+
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// Draw a bar showing how much of the disc is used, with a segment for each
+/// track colored by its format.
+fn capacity_bar(ui: &mut egui::Ui, disc: &Disc) {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), 10.0),
+        egui::Sense::hover(),
+    );
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    // The amount of SP disc time each track takes up
+    let segments: Vec<(Duration, UploadFormat)> = disc.tracks().iter()
+        .map(|t| {
+            let format = UploadFormat::of_track(t);
+            (t.duration().as_duration() / format.time_multiplier(), format)
+        })
+        .collect();
+
+    let total: Duration = segments.iter().map(|(d, _)| *d).sum::<Duration>() + disc_free_time(disc);
+    if total.is_zero() {
+        return;
+    }
+
+    let mut x = rect.left();
+    for (duration, format) in segments {
+        let width = rect.width() * (duration.as_secs_f32() / total.as_secs_f32());
+        let segment = egui::Rect::from_min_size(egui::pos2(x, rect.top()), egui::vec2(width, rect.height()));
+        painter.rect_filled(segment.shrink2(egui::vec2(0.5, 0.0)), 0.0, format.color());
+        x += width;
+    }
+
+    response.on_hover_ui(|ui| {
+        for format in UploadFormat::ALL {
+            ui.horizontal(|ui| {
+                let (swatch, _) = ui.allocate_exact_size(egui::vec2(10.0, 10.0), egui::Sense::hover());
+                ui.painter().rect_filled(swatch, 2.0, format.color());
+                ui.label(format.to_string());
+            });
+        }
+    });
+}
+
+/// Draw every track on the disc as a segment of one bar, with a playhead at
+/// the current track and time. Returns the track that was clicked, if any.
+fn disc_timeline(ui: &mut egui::Ui, disc: &Disc, position: Option<(usize, Duration)>) -> Option<usize> {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), 16.0),
+        egui::Sense::click(),
+    );
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let durations: Vec<Duration> = disc.tracks().iter().map(|t| t.duration().as_duration()).collect();
+    let total: Duration = durations.iter().sum();
+    if total.is_zero() {
+        return None;
+    }
+
+    let x_at = |time: Duration| rect.left() + rect.width() * (time.as_secs_f32() / total.as_secs_f32());
+
+    let mut start = Duration::ZERO;
+    let mut clicked = None;
+    for (index, (duration, track)) in durations.iter().zip(disc.tracks().iter()).enumerate() {
+        let segment = egui::Rect::from_x_y_ranges(x_at(start)..=x_at(start + *duration), rect.y_range());
+        let playing = position.is_some_and(|(p, _)| p == index);
+
+        let color = UploadFormat::of_track(track).color();
+        let color = if playing { color } else { color.gamma_multiply(0.6) };
+        painter.rect_filled(segment.shrink2(egui::vec2(0.5, 0.0)), 0.0, color);
+
+        if let Some(pos) = response.hover_pos()
+            && segment.contains(pos)
+        {
+            response.clone().on_hover_text(format!("{}. {}", index + 1, track_title(track, false)));
+            if response.clicked() {
+                clicked = Some(index);
+            }
+        }
+
+        start += *duration;
+    }
+
+    if let Some((track, time)) = position
+        && track < durations.len()
+    {
+        let elapsed = durations[..track].iter().sum::<Duration>() + time.min(durations[track]);
+        painter.vline(x_at(elapsed), rect.y_range(), egui::Stroke::new(2.0, ui.visuals().strong_text_color()));
+    }
+
+    clicked
+}
+
+/// How far through a transfer is, from 0 to 1. Devices can report a total of
+/// zero before they know it, or a little more done than the total.
+fn transfer_fraction(done: usize, out_of: usize) -> f32 {
+    if out_of == 0 {
+        return 0.0;
+    }
+
+    (done as f32 / out_of as f32).clamp(0.0, 1.0)
+}
+
+/// The remaining recording time on a disc, in SP
+fn disc_free_time(disc: &Disc) -> Duration {
+    disc.left().as_duration()
+}
+
+/// Make sure a track of the given length will fit in the disc's free space,
+/// suggesting a more compressed format if it won't.
+fn check_fits(disc: &Disc, length: Duration, format: UploadFormat) -> Result<()> {
+    let free = disc_free_time(disc);
+    if length <= free * format.time_multiplier() {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "Needs {} in {format} but only {} is free",
+        pretty_duration(length, DurationFormat::Compact),
+        pretty_duration(free * format.time_multiplier(), DurationFormat::Compact),
+    );
+
+    // Suggest the least compressed format that would fit
+    let fits = UploadFormat::ALL.into_iter()
+        .find(|f| f.time_multiplier() > format.time_multiplier() && length <= free * f.time_multiplier());
+    if let Some(fits) = fits {
+        message.push_str(&format!(", try uploading in {fits}"));
+    }
+
+    Err(anyhow!(message))
+}
+
+/// Estimate whether all of the disc's track titles plus a new disc title will
+/// fit in the table of contents.
+fn titles_fit_in_toc(disc: &Disc, disc_title: &str) -> bool {
+    let titles: Vec<&str> = disc.tracks().iter()
+        .map(|t| t.title().as_str())
+        .chain(std::iter::once(disc_title))
+        .collect();
+
+    fit_in_toc(&titles)
+}
+
+#[derive(Default)]
+struct PlayerState {
+    connected: bool,
+    /// Set while the worker thread is opening the device
+    connecting: bool,
+    reading: bool,
+
+    disc_contents: Option<Disc>,
+    device_state: Option<DeviceStatus>,
+    progress: Option<f32>,
+    /// Set while waiting for the device to be ready to receive audio
+    preparing_transfer: bool,
+    /// How fast the current upload is going, once that's known
+    transfer_speed: Option<TransferSpeed>,
+    /// The file being uploaded and the total number of files in the queue
+    queue_position: Option<(usize, usize)>,
+    /// The path and title of the file being uploaded
+    current_upload: Option<(PathBuf, String)>,
+    /// Failures from the most recent upload queue
+    upload_errors: Vec<String>,
+    /// The most recent error to show to the user
+    last_error: Option<String>,
+    /// Recent errors, oldest first
+    error_log: VecDeque<String>,
+    /// Recent commands run on the device and their results, oldest first
+    command_log: VecDeque<CommandLogEntry>,
+    /// Which attempt at reconnecting to a lost device is underway
+    reconnect_attempt: Option<u32>,
+    /// The model name of the connected device
+    device_name: Option<String>,
+    /// The vendor and product IDs of the connected device
+    usb_id: Option<(u16, u16)>,
+    /// Whether the device can eject discs by itself
+    can_eject: bool,
+    repeat_mode: RepeatMode,
+    /// Stop once the playing track finishes, then turn this off again
+    stop_after_track: bool,
+    ab_loop: Option<AbLoop>,
+    /// The disc's track groups, in disc order
+    groups: Vec<TrackGroup>,
+    /// Whether the disc's write-protect tab is set
+    write_protected: bool,
+    /// When the device last answered a status poll
+    last_status: Option<Instant>,
+    /// Why the disc's contents couldn't be read, if they couldn't
+    toc_error: Option<String>,
+    /// The contents shown are from the last time this disc was read, and
+    /// are being read again to make sure they're still right
+    cached_contents: bool,
+    /// Tracks the worker thread is in the middle of changing
+    busy_tracks: HashSet<usize>,
+    /// How to reverse the most recent edit
+    undo: Option<Undo>,
+}
+
+/// How fast a transfer is going
+#[derive(Debug, Clone, Copy)]
+struct TransferSpeed {
+    /// Seconds of audio sent per second
+    realtime: f32,
+    remaining: Duration,
+}
+
+impl TransferSpeed {
+    /// Work out the speed from how much has been sent so far, or `None` if
+    /// too little has been sent to tell yet.
+    fn measure(elapsed: Duration, done: usize, total: usize, bytes_per_second: f64) -> Option<Self> {
+        if done == 0 || elapsed < Duration::from_millis(500) {
+            return None;
+        }
+
+        let rate = done as f64 / elapsed.as_secs_f64();
+        Some(Self {
+            realtime: (rate / bytes_per_second) as f32,
+            remaining: Duration::from_secs_f64(total.saturating_sub(done) as f64 / rate),
+        })
+    }
+}
+
+/// A command run on the device, and how it turned out
+struct CommandLogEntry {
+    /// How long after the app started the command finished
+    time: Duration,
+    command: String,
+    result: Result<(), String>,
+}
+
+/// A command which reverses an edit, and what the edit was
+struct Undo {
+    description: String,
+    command: PlayerCommand,
+}
+
+impl PlayerState {
+    /// The track the device is on, its length and how far into it playback
+    /// is, as long as that track is in the contents as last read. The two can
+    /// disagree for a moment after the disc changes.
+    fn current_track(&self) -> Option<(usize, Duration, Duration)> {
+        let status = self.device_state?;
+        let index = status.track as usize;
+        let length = self.disc_contents.as_ref()?.tracks().get(index)?.duration().as_duration();
+
+        Some((index, length, Duration::from(status.time)))
+    }
+
+    /// The track being played, paused or searched through.
+    fn playing_track(&self) -> Option<usize> {
+        let state = self.device_state?.state?;
+        if !matches!(state, OS::Playing | OS::Paused | OS::FastForward | OS::Rewind) {
+            return None;
+        }
+
+        self.current_track().map(|(index, _, _)| index)
+    }
+
+    /// The track Next would move to from the current track, if there is one.
+    /// NetMD devices don't wrap around from the last track, whatever the
+    /// repeat mode.
+    fn next_track(&self) -> Option<usize> {
+        let (current, _, _) = self.current_track()?;
+        let track_count = self.disc_contents.as_ref()?.track_count() as usize;
+
+        (current + 1 < track_count).then_some(current + 1)
+    }
+
+    /// Show an error to the user and add it to the error log.
+    fn report_error(&mut self, error: String) {
+        self.log_error(error.clone());
+        self.last_error = Some(error);
+    }
+
+    /// Add an error to the log without bothering the user with it.
+    fn log_error(&mut self, error: String) {
+        log::error!("{error}");
+
+        if self.error_log.len() >= ERROR_LOG_LENGTH {
+            self.error_log.pop_front();
+        }
+        self.error_log.push_back(error);
+    }
+
+    /// Add a finished command to the command log.
+    fn log_command(&mut self, command: String, result: Result<(), String>) {
+        if self.command_log.len() >= COMMAND_LOG_LENGTH {
+            self.command_log.pop_front();
+        }
+        self.command_log.push_back(CommandLogEntry {
+            time: STARTED.elapsed(),
+            command,
+            result,
+        });
+    }
+
+    /// Forget everything about the connection, but keep the errors so the
+    /// user can still see what went wrong.
+    fn reset(&mut self) {
+        *self = Self {
+            last_error: self.last_error.take(),
+            error_log: std::mem::take(&mut self.error_log),
+            command_log: std::mem::take(&mut self.command_log),
+            ..Default::default()
+        };
+    }
+}
+
+/// The number of errors kept in the error log
+const ERROR_LOG_LENGTH: usize = 100;
+
+/// The number of commands kept in the command log
+const COMMAND_LOG_LENGTH: usize = 500;
+
+/// How long to wait before the first retry of a failed device call
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How long to wait for the device to be ready before giving up on an upload
+const TRANSFER_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the device can go without answering a status poll before the
+/// connection is shown as stalled
+const STALE_STATUS_AFTER: Duration = Duration::from_secs(5);
+
+/// How often to check whether the disc was swapped for another
+const DISC_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The bit of the disc flags which is set when the write-protect tab is open
+const DISC_FLAG_WRITE_PROTECTED: u8 = 0x40;
+
+/// Identify a disc by its raw title, how many tracks it has and how much of
+/// it is recorded on. Untitled discs with the same number of tracks are
+/// still told apart by their total length.
+fn disc_fingerprint(raw_title: &str, track_count: u16, used: Duration) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (raw_title, track_count, used).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A random number below `n`, which is plenty for shuffling tracks.
+fn random_below(n: usize) -> usize {
+    (RandomState::new().hash_one(Instant::now()) % n as u64) as usize
+}
+
+/// A disc's contents as they were last read
+#[derive(Clone)]
+struct CachedDisc {
+    contents: Disc,
+    groups: Vec<TrackGroup>,
+    write_protected: bool,
+}
+
+/// Disc contents by [`disc_fingerprint`], so a disc seen before can be shown
+/// without waiting for it to be read
+type DiscCache = Arc<RwLock<HashMap<u64, CachedDisc>>>;
+
+/// A section of a track to play over and over
+#[derive(Debug, Clone, Copy)]
+struct AbLoop {
+    track: u8,
+    start: Duration,
+    end: Duration,
+}
+
+/// What to play again once playback reaches the end of a track or the disc
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum RepeatMode {
+    #[default]
+    None,
+    Track,
+    Disc,
+    /// Play every track once in a random order
+    Shuffle,
+}
+
+/// A track to be read from disk and sent to the device
+#[derive(Debug)]
+struct UploadJob {
+    path: PathBuf,
+    title: String,
+    full_width_title: Option<String>,
+    format: UploadFormat,
+    level: Level,
+}
+
+impl UploadJob {
+    /// Read the audio for this job from disk, decoding WAV files, ready to be
+    /// sent in chunks of the given size.
+    fn read_track(self, chunk_size: usize) -> Result<MDTrack> {
+        let is_wav = self.path.extension().is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+        let data = if is_wav {
+            read_wav(&self.path, self.format, self.level)
+        } else {
+            std::fs::read(&self.path).map_err(anyhow::Error::from)
+        }.map_err(|e| anyhow!("Could not read {}: {e}", self.path.display()))?;
+
+        Ok(MDTrack {
+            chunk_size,
+            title: sanitize_title(&self.title),
+            full_width_title: self.full_width_title.as_deref().map(sanitize_title),
+            format: self.format.wire_format(),
+            data,
+        })
+    }
+}
+
+/// The sample rate NetMD devices expect PCM audio in
+const PCM_SAMPLE_RATE: u32 = 44_100;
+
+/// Decode a WAV file into the 16 bit big-endian stereo PCM sent to the
+/// device for SP uploads, adjusting its volume on the way.
+fn read_wav(path: &Path, format: UploadFormat, level: Level) -> Result<Vec<u8>> {
+    let mut reader = hound::WavReader::open(path)?;
+    if let Some(problem) = wav_problem(&reader.spec(), format) {
+        return Err(anyhow!(problem));
+    }
+
+    let mut samples = reader.samples::<i16>().collect::<Result<Vec<i16>, _>>()?;
+    adjust_level(&mut samples, level);
+
+    Ok(samples.iter().flat_map(|s| s.to_be_bytes()).collect())
+}
+
+/// A change to a track's volume, made before it's uploaded
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Level {
+    #[default]
+    Unchanged,
+    /// Louder or quieter by a number of decibels
+    Gain(f32),
+    /// As loud as possible without clipping, leaving a little headroom
+    Normalize,
+}
+
+/// The peak level tracks are normalized to, in decibels below full scale
+const NORMALIZE_PEAK_DB: f32 = -1.0;
+
+/// Scale 16 bit samples according to a level adjustment, clipping anything
+/// pushed out of range.
+fn adjust_level(samples: &mut [i16], level: Level) {
+    let gain_db = match level {
+        Level::Unchanged => return,
+        Level::Gain(db) => db,
+        Level::Normalize => {
+            let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+            if peak == 0 {
+                return;
+            }
+            let peak_db = 20.0 * (peak as f32 / i16::MAX as f32).log10();
+            NORMALIZE_PEAK_DB - peak_db
+        }
+    };
+
+    let factor = 10f32.powf(gain_db / 20.0);
+    for sample in samples {
+        *sample = (*sample as f32 * factor).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+}
+
+/// Choose how to change a track's volume.
+fn level_picker(ui: &mut egui::Ui, level: &mut Level) {
+    ui.horizontal(|ui| {
+        let is_gain = matches!(level, Level::Gain(_));
+        if ui.selectable_label(*level == Level::Unchanged, "Unchanged").clicked() {
+            *level = Level::Unchanged;
+        }
+        if ui.selectable_label(is_gain, "Gain").clicked() && !is_gain {
+            *level = Level::Gain(0.0);
+        }
+        if ui.selectable_label(*level == Level::Normalize, "Normalize")
+            .on_hover_text(format!("Make the loudest part {NORMALIZE_PEAK_DB} dB below full scale"))
+            .clicked()
+        {
+            *level = Level::Normalize;
+        }
+
+        if let Level::Gain(db) = level {
+            ui.add(egui::DragValue::new(db).range(-20.0..=20.0).speed(0.1).suffix(" dB"));
+        }
+    });
+}
+
+/// Why a WAV file can't be uploaded as it is, if it can't. Sending audio in
+/// any other layout would record noise.
+fn wav_problem(spec: &hound::WavSpec, format: UploadFormat) -> Option<String> {
+    if format != UploadFormat::SP {
+        return Some(format!("Uncompressed audio can only be uploaded in SP, not {format}"));
+    }
+
+    if spec.sample_format != hound::SampleFormat::Int
+        || spec.bits_per_sample != 16
+        || spec.channels != 2
+        || spec.sample_rate != PCM_SAMPLE_RATE
+    {
+        return Some(format!(
+            "Expected 16 bit stereo audio at 44.1kHz, but the file is {}",
+            describe_wav(spec),
+        ));
+    }
+
+    None
+}
+
+/// A short description of a WAV file's format, like "16 bit stereo 44.1kHz"
+fn describe_wav(spec: &hound::WavSpec) -> String {
+    let channels = match spec.channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        n => format!("{n} channel"),
+    };
+    let float = if spec.sample_format == hound::SampleFormat::Float { " float" } else { "" };
+
+    format!(
+        "{} bit{float} {channels} {}kHz",
+        spec.bits_per_sample,
+        spec.sample_rate as f32 / 1000.0,
+    )
+}
+
+#[derive(Debug)]
+enum PlayerCommand {
+    Disconnect,
+    /// Play, pause or search from wherever the device is now
+    Playback(Action),
+    Stop,
+    /// Move forwards or backwards by a number of tracks
+    SkipTracks(isize),
+    /// Play from the start of a track
+    GoToTrack(usize),
+    Seek(Duration),
+    /// Start or stop playing part of a track over and over
+    SetLoop(Option<AbLoop>),
+    SetRepeatMode(RepeatMode),
+    SetStopAfterTrack(bool),
+    Upload(UploadJob),
+    UploadQueue(Vec<UploadJob>),
+    CancelUpload,
+    DownloadTrack { index: usize, dest: PathBuf },
+    RenameTrack { index: usize, title: String },
+    /// Set the titles of several tracks, reading the disc again only once
+    /// they're done
+    RenameTracks(Vec<(usize, TrackTitles)>),
+    SetDiscTitle(String),
+    MoveTrack { from: usize, to: usize },
+    /// Group a range of tracks, both ends inclusive
+    CreateGroup { first: usize, last: usize, title: String },
+    RenameGroup { group: usize, title: String },
+    /// Move a track to the end of a group, or into a new group of its own if
+    /// `group` is `None`
+    MoveTrackToGroup { track: usize, group: Option<usize> },
+    Ungroup(usize),
+    EraseDisc,
+    Eject,
+    Delete(Vec<usize>),
+    /// Read the status and contents again, in case they changed behind our back
+    Refresh,
+}
+
+impl PlayerCommand {
+    /// The tracks this command changes, which are shown as busy until it
+    /// finishes
+    fn affected_tracks(&self) -> HashSet<usize> {
+        match self {
+            Self::RenameTrack { index, .. } | Self::DownloadTrack { index, .. } => HashSet::from([*index]),
+            // Every track in between shifts along by one
+            Self::MoveTrack { from, to } => (*from.min(to)..=*from.max(to)).collect(),
+            Self::MoveTrackToGroup { track, .. } => HashSet::from([*track]),
+            Self::RenameTracks(renames) => renames.iter().map(|(index, _)| *index).collect(),
+            Self::Delete(tracks) => tracks.iter().copied().collect(),
+            _ => HashSet::new(),
+        }
+    }
+
+    /// Whether this command writes to the disc
+    fn changes_disc(&self) -> bool {
+        matches!(self,
+            Self::Upload(_)
+            | Self::UploadQueue(_)
+            | Self::RenameTrack { .. }
+            | Self::RenameTracks(_)
+            | Self::SetDiscTitle(_)
+            | Self::MoveTrack { .. }
+            | Self::CreateGroup { .. }
+            | Self::RenameGroup { .. }
+            | Self::MoveTrackToGroup { .. }
+            | Self::Ungroup(_)
+            | Self::EraseDisc
+            | Self::Delete(_)
+        )
+    }
+
+    /// What to tell the user if the device rejects this command
+    fn failure_message(&self) -> String {
+        match self {
+            Self::Disconnect => "Could not disconnect".to_string(),
+            Self::Playback(_) => "Could not change playback".to_string(),
+            Self::Stop => "Could not stop playback".to_string(),
+            Self::SkipTracks(_) => "Could not skip to another track".to_string(),
+            Self::GoToTrack(index) => format!("Could not play track {}", index + 1),
+            Self::Seek(_) => "Could not seek within the track".to_string(),
+            Self::SetLoop(_) => "Could not change the loop".to_string(),
+            Self::SetRepeatMode(_) => "Could not change the repeat mode".to_string(),
+            Self::SetStopAfterTrack(_) => "Could not change when playback stops".to_string(),
+            Self::Upload(job) => format!("Could not upload {}", job.path.display()),
+            Self::UploadQueue(_) => "Could not finish uploading".to_string(),
+            Self::CancelUpload => "Could not cancel the upload".to_string(),
+            Self::DownloadTrack { index, .. } => format!("Could not save track {}", index + 1),
+            Self::RenameTrack { index, .. } => format!("Could not rename track {}", index + 1),
+            Self::RenameTracks(_) => "Could not rename the tracks".to_string(),
+            Self::SetDiscTitle(_) => "Could not rename the disc".to_string(),
+            Self::MoveTrack { from, .. } => format!("Could not move track {}", from + 1),
+            Self::CreateGroup { .. } => "Could not create the group".to_string(),
+            Self::RenameGroup { .. } => "Could not rename the group".to_string(),
+            Self::MoveTrackToGroup { track, .. } => format!("Could not move track {} to the group", track + 1),
+            Self::Ungroup(_) => "Could not remove the group".to_string(),
+            Self::EraseDisc => "Could not erase the disc".to_string(),
+            Self::Eject => "Could not eject the disc".to_string(),
+            Self::Delete(tracks) if tracks.len() == 1 => format!("Could not delete track {}", tracks[0] + 1),
+            Self::Delete(_) => "Could not delete the tracks".to_string(),
+            Self::Refresh => "Could not read the disc".to_string(),
+        }
+    }
+}
+
+/// What tells a USB device apart from others, even after it's unplugged and
+/// plugged back in
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeviceIdentity {
+    vendor_id: u16,
+    product_id: u16,
+    serial_number: Option<String>,
+}
+
+impl DeviceIdentity {
+    async fn of(device: &DeviceInfo) -> Self {
+        Self {
+            vendor_id: device.vendor_id().await,
+            product_id: device.product_id().await,
+            serial_number: device.serial_number().await,
+        }
+    }
+}
+
+struct MinidiscThread {
+    device: NetMDContext,
+    state: Arc<RwLock<PlayerState>>,
+    recv: async_channel::Receiver<PlayerCommand>,
+    /// Set when the user has asked for the current upload to stop
+    cancel: Arc<AtomicBool>,
+    /// Commands sent during a transfer, to be run once it's over
+    deferred: VecDeque<PlayerCommand>,
+    /// The track which was playing at the last status poll, used to notice
+    /// when playback moves on by itself
+    last_playing: Option<u8>,
+    settings: Arc<RwLock<DeviceSettings>>,
+    /// Identifies the disc the contents were last read from
+    disc_fingerprint: Option<u64>,
+    disc_cache: DiscCache,
+    /// Tracks already played since shuffling started
+    shuffle_played: HashSet<u8>,
+    /// The device to reconnect to if it's lost, so it's the same one the
+    /// user picked when several are attached
+    identity: DeviceIdentity,
+}
+
+impl MinidiscThread {
+    async fn minidisc_thread(
+        comm: Arc<RwLock<PlayerState>>,
+        recv: async_channel::Receiver<PlayerCommand>,
+        device: Option<DeviceInfo>,
+        settings: Arc<RwLock<DeviceSettings>>,
+        disc_cache: DiscCache,
+    ) {
+        let (md_dev, identity) = match Self::open_device(device).await {
+            Ok(d) => d,
+            Err(e) => {
+                let mut state = comm.write().unwrap();
+                state.connected = false;
+                state.connecting = false;
+                state.report_error(format!("Could not connect: {e}"));
+                return;
+            }
+        };
+
+        let mut new_self = Self {
+            device: md_dev,
+            state: comm,
+            recv,
+            cancel: Arc::new(AtomicBool::new(false)),
+            deferred: VecDeque::new(),
+            last_playing: None,
+            settings,
+            disc_fingerprint: None,
+            disc_cache,
+            shuffle_played: HashSet::new(),
+            identity,
+        };
+
+        loop {
+            let device = &new_self.device.interface().device;
+            let device_name = device.device_name().clone();
+            let usb_id = (*device.vendor_id(), *device.product_id());
+            log::info!("Connected to {:?} ({:04x}:{:04x})", device_name, usb_id.0, usb_id.1);
+
+            let mut state = new_self.state.write().unwrap();
+            state.connected = true;
+            state.connecting = false;
+            state.device_name = device_name;
+            state.usb_id = Some(usb_id);
+            drop(state);
+
+            match new_self.command_loop().await {
+                Ok(_) => break,
+                Err(e) => new_self.state.write().unwrap().report_error(format!("A fatal error occurred: {e}")),
+            }
+
+            new_self.state.write().unwrap().reset();
+            // They were meant for the device that was lost
+            new_self.deferred.clear();
+            if !new_self.reconnect().await {
+                break;
+            }
+        }
+
+        new_self.state.write().unwrap().reset();
+    }
+
+    /// Keep trying to connect to a device again after losing it, if the user
+    /// has enabled that. Returns `true` once a device is connected.
+    async fn reconnect(&mut self) -> bool {
+        let settings = *self.settings.read().unwrap();
+        if !settings.auto_reconnect {
+            return false;
+        }
+
+        for attempt in 1..=settings.reconnect_attempts {
+            self.state.write().unwrap().reconnect_attempt = Some(attempt);
+            async_io::Timer::after(settings.reconnect_interval).await;
+
+            // Give up if the user disconnected while waiting
+            loop {
+                match self.recv.try_recv() {
+                    Ok(PlayerCommand::Disconnect) | Err(async_channel::TryRecvError::Closed) => return false,
+                    Ok(_) => (),
+                    Err(async_channel::TryRecvError::Empty) => break,
+                }
+            }
+
+            match Self::reopen_device(&self.identity).await {
+                Ok(device) => {
+                    self.device = device;
+                    self.state.write().unwrap().reconnect_attempt = None;
+                    return true;
+                }
+                Err(e) => log::info!("Reconnect attempt {attempt} failed: {e}"),
+            }
+        }
+
+        self.state.write().unwrap().report_error(format!(
+            "Could not reconnect after {} attempts",
+            settings.reconnect_attempts
+        ));
+
+        false
+    }
+
+    /// Open the given USB device, or the first supported one found, as a
+    /// NetMD device, along with what identifies it.
+    async fn open_device(device: Option<DeviceInfo>) -> Result<(NetMDContext, DeviceIdentity)> {
+        let device = match device {
+            Some(device) => device,
+            None => cross_usb::get_device_list(DEVICE_IDS_CROSSUSB.to_vec()).await
+                .ok()
+                .and_then(|mut devices| devices.next())
+                .ok_or_else(|| anyhow!("No supported device was found"))?,
+        };
+
+        let identity = DeviceIdentity::of(&device).await;
+        Ok((NetMDContext::new(device.open().await?).await?, identity))
+    }
+
+    /// Open the device with the given identity again, ignoring any others
+    /// which are attached.
+    async fn reopen_device(identity: &DeviceIdentity) -> Result<NetMDContext> {
+        let devices = cross_usb::get_device_list(DEVICE_IDS_CROSSUSB.to_vec()).await
+            .map_err(|_| anyhow!("Could not list USB devices"))?;
+
+        for device in devices {
+            if DeviceIdentity::of(&device).await == *identity {
+                return Ok(NetMDContext::new(device.open().await?).await?);
+            }
+        }
+
+        Err(anyhow!("The device isn't attached"))
+    }
+
+    /// Whether the disc in the drive looks different from the one last read.
+    /// Errors are taken to mean the disc can't be checked right now.
+    async fn disc_changed(&mut self) -> bool {
+        match self.read_fingerprint().await {
+            Ok(fingerprint) => self.disc_fingerprint != Some(fingerprint),
+            Err(_) => false,
+        }
+    }
+
+    /// Work out the fingerprint of the disc in the drive without reading its
+    /// tracks, which is cheap enough to do every [`DISC_CHECK_INTERVAL`].
+    /// This is the only place fingerprints are made, so a disc that hasn't
+    /// changed always gives the same one.
+    async fn read_fingerprint(&mut self) -> Result<u64> {
+        let track_count = self.device.interface_mut().track_count().await?;
+        let raw_title = self.device.interface_mut().disc_title(false).await?;
+        let [used, ..] = self.device.interface_mut().disc_capacity().await?;
+
+        Ok(disc_fingerprint(&raw_title, track_count, used.as_duration()))
+    }
+
+    async fn get_contents(&mut self) -> Result<()> {
+        self.state.write().unwrap().reading = true;
+        let contents = match self.device.list_content().await {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.state.write().unwrap().reading = false;
+                self.state.write().unwrap().cached_contents = false;
+
+                // A device which still answers just has a disc it can't make sense of
+                self.device.device_status().await?;
+                let mut state = self.state.write().unwrap();
+                state.disc_contents = None;
+                state.toc_error = Some(e.to_string());
+                return Ok(());
+            }
+        };
+        let raw_title = self.device.interface_mut().disc_title(false).await?;
+        let flags = self.device.interface_mut().disc_flags().await?;
+
+        let fingerprint = self.read_fingerprint().await?;
+        let mut grouped = GroupedTitle::parse(&raw_title);
+        grouped.clamp(contents.track_count() as usize);
+        let cached = CachedDisc {
+            contents,
+            groups: grouped.groups,
+            write_protected: flags & DISC_FLAG_WRITE_PROTECTED != 0,
+        };
+        self.disc_cache.write().unwrap().insert(fingerprint, cached.clone());
+        self.disc_fingerprint = Some(fingerprint);
+
+        self.state.write().unwrap().disc_contents = Some(cached.contents);
+        self.state.write().unwrap().groups = cached.groups;
+        self.state.write().unwrap().write_protected = cached.write_protected;
+        self.state.write().unwrap().toc_error = None;
+        self.state.write().unwrap().cached_contents = false;
+        self.state.write().unwrap().reading = false;
+
+        Ok(())
+    }
+
+    /// Read the contents of a disc which has just been put in, or was already
+    /// there when connecting. A disc read before this session is shown from
+    /// the cache straight away, and then read again in case it was changed
+    /// on another device.
+    async fn read_new_disc(&mut self) -> Result<()> {
+        self.shuffle_played.clear();
+
+        if let Ok(fingerprint) = self.read_fingerprint().await {
+            let cached = self.disc_cache.read().unwrap().get(&fingerprint).cloned();
+
+            if let Some(cached) = cached {
+                let mut state = self.state.write().unwrap();
+                state.disc_contents = Some(cached.contents);
+                state.groups = cached.groups;
+                state.write_protected = cached.write_protected;
+                state.cached_contents = true;
+            }
+        }
+
+        self.get_contents().await
+    }
+
+    /// Forget the cached contents of the disc in the drive, before it's
+    /// changed, so they're never shown out of date.
+    fn forget_cached_disc(&mut self) {
+        if let Some(fingerprint) = self.disc_fingerprint {
+            self.disc_cache.write().unwrap().remove(&fingerprint);
+        }
+    }
+
+    /// Send a track to the device, returning `false` if the user cancelled
+    /// the transfer.
+    async fn upload(&mut self, track: MDTrack, format: UploadFormat) -> Result<bool> {
+        // Running out of space partway through would leave half a track behind
+        let length = Duration::from_secs_f64(track.data.len() as f64 / format.bytes_per_second());
+        if let Some(dc) = &self.state.read().unwrap().disc_contents {
+            check_fits(dc, length, format)?;
+        }
+
+        self.device.interface_mut().stop().await?;
+        let track_count = self.device.interface_mut().track_count().await?;
+
+        self.state.write().unwrap().preparing_transfer = true;
+        let ready = self.wait_until_ready().await;
+        self.state.write().unwrap().preparing_transfer = false;
+        ready?;
+
+        self.cancel.store(false, Ordering::Relaxed);
+        let player_state_thread = Arc::clone(&self.state);
+        let cancel_thread = Arc::clone(&self.cancel);
+        let started = Instant::now();
+        let transfer = self.device.download(track, move |out_of: usize, done: usize| {
+            if cancel_thread.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let mut state = player_state_thread.write().unwrap();
+            state.progress = Some(transfer_fraction(done, out_of));
+            state.transfer_speed = TransferSpeed::measure(started.elapsed(), done, out_of, format.bytes_per_second());
+        });
+
+        let result = cancellable(&self.recv, &self.cancel, &mut self.deferred, transfer).await;
+        self.state.write().unwrap().progress = None;
+        self.state.write().unwrap().transfer_speed = None;
+
+        match result {
+            Some(Ok(_)) => Ok(true),
+            Some(Err(e)) => {
+                if self.remove_partial_track(track_count).await {
+                    Err(anyhow!("{e}, the incomplete track was removed"))
+                } else {
+                    Err(e.into())
+                }
+            }
+            None => {
+                log::info!("Upload cancelled");
+                self.device.interface_mut().stop().await?;
+                self.remove_partial_track(track_count).await;
+                Ok(false)
+            }
+        }
+    }
+
+    /// The number of tracks on the disc, as of the last time it was read
+    fn track_count(&self) -> usize {
+        self.state.read().unwrap().disc_contents.as_ref().map_or(0, |dc| dc.track_count() as usize)
+    }
+
+    /// Play the first track added since the disc had `previous_count` tracks,
+    /// if the user asked for uploads to be played straight away.
+    async fn play_after_upload(&mut self, previous_count: usize) -> Result<()> {
+        if !self.settings.read().unwrap().play_after_upload || self.track_count() <= previous_count {
+            return Ok(());
+        }
+
+        self.device.interface_mut().go_to_track(previous_count as u16).await?;
+        self.device.interface_mut().playback_control(Action::Play).await?;
+
+        Ok(())
+    }
+
+    /// Erase the track a stopped upload left behind, if it got as far as
+    /// creating one. Returns whether a track was removed.
+    async fn remove_partial_track(&mut self, previous_count: u16) -> bool {
+        let Ok(track_count) = self.device.interface_mut().track_count().await else {
+            return false;
+        };
+        if track_count <= previous_count {
+            return false;
+        }
+
+        // New tracks are always added at the end
+        match self.device.interface_mut().erase_track(previous_count).await {
+            Ok(_) => {
+                log::info!("Removed incomplete track {}", previous_count + 1);
+                true
+            }
+            Err(e) => {
+                self.state.write().unwrap().log_error(format!("Could not remove the incomplete track: {e}"));
+                false
+            }
+        }
+    }
+
+    /// Wait for the device to settle after stopping, so audio isn't sent while
+    /// it's still busy. Many devices only report `ReadyForTransfer` once the
+    /// transfer has started, so `Ready` is good enough too.
+    async fn wait_until_ready(&mut self) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            let status = self.device.device_status().await?;
+            self.state.write().unwrap().device_state = Some(status);
+
+            if matches!(status.state, Some(OS::Ready | OS::ReadyForTransfer)) {
+                return Ok(());
+            }
+            if started.elapsed() >= TRANSFER_READY_TIMEOUT {
+                return Err(anyhow!("The device didn't become ready to receive audio, try again once it has stopped"));
+            }
+
+            async_io::Timer::after(Duration::from_millis(250)).await;
+        }
+    }
+
+    /// Read a track off the disc and save it to `dest`.
+    ///
+    /// Failures are reported to the user rather than returned, as most devices refuse to
+    /// read tracks back at all.
+    async fn download_track(&mut self, index: usize, dest: PathBuf) -> Result<()> {
+        self.device.interface_mut().stop().await?;
+
+        self.cancel.store(false, Ordering::Relaxed);
+        let player_state_thread = Arc::clone(&self.state);
+        let transfer = self.device.upload(index as u16, Some(move |out_of: usize, done: usize| {
+            player_state_thread.write().unwrap().progress = Some(transfer_fraction(done, out_of))
+        }));
+
+        let result = cancellable(&self.recv, &self.cancel, &mut self.deferred, transfer).await;
+        self.state.write().unwrap().progress = None;
+
+        let error = match result {
+            Some(Ok((_, data))) => std::fs::write(&dest, data)
+                .err()
+                .map(|e| format!("Could not write {}: {e}", dest.display())),
+            Some(Err(e)) => Some(format!(
+                "The device refused to read track {}: {e}. Most NetMD devices block \
+                reading tracks back to a computer for copyright reasons.",
+                index + 1
+            )),
+            None => {
+                log::info!("Download cancelled");
+                self.device.interface_mut().stop().await?;
+                None
+            }
+        };
+
+        if let Some(error) = error {
+            self.state.write().unwrap().report_error(error);
+        }
+
+        Ok(())
+    }
+
+    /// Set a track's title, falling back to the full-width title for any
+    /// characters a half-width title can't hold.
+    async fn rename_track(&mut self, index: usize, title: &str) -> Result<()> {
+        let title = &sanitize_title(title);
+        let half_width = half_width_title(title);
+        self.device.interface_mut().set_track_title(index as u16, &half_width, false).await?;
+
+        if half_width != title {
+            self.device.interface_mut().set_track_title(index as u16, title, true).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write whichever of a track's titles differ from its current ones,
+    /// leaving the other as it is.
+    async fn set_track_titles(&mut self, index: usize, titles: &TrackTitles, current: &TrackTitles) -> Result<()> {
+        let half_width = half_width_title(&sanitize_title(&titles.title));
+        if half_width != current.title {
+            self.device.interface_mut().set_track_title(index as u16, &half_width, false).await?;
+        }
+
+        let full_width = sanitize_title(&titles.full_width_title);
+        if full_width != current.full_width_title {
+            self.device.interface_mut().set_track_title(index as u16, &full_width, true).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Change the disc's groups, which are stored in the raw disc title.
+    async fn edit_groups(&mut self, edit: impl FnOnce(&mut Vec<TrackGroup>)) -> Result<()> {
+        let raw_title = self.device.interface_mut().disc_title(false).await?;
+        let track_count = self.device.interface_mut().track_count().await?;
+        let mut title = GroupedTitle::parse(&raw_title);
+        title.clamp(track_count as usize);
+        let unedited = title.groups.clone();
+
+        edit(&mut title.groups);
+        title.groups.sort_by_key(|g| g.first);
+
+        // Leave the title alone unless there's something to write, so it's
+        // never rewritten just for being parsed
+        if title.groups != unedited {
+            self.device.interface_mut().set_disc_title(&title.to_raw(), false).await?;
+        }
+        self.get_contents().await
+    }
+
+    /// Make a call to the device, retrying with a growing delay if it fails
+    /// since USB communication sometimes fails once and then recovers. Only
+    /// for calls which do the same thing however many times they're made.
+    async fn retry<T>(&mut self, mut call: impl AsyncFnMut(&mut NetMDContext) -> Result<T>) -> Result<T> {
+        let attempts = self.settings.read().unwrap().retry_attempts;
+        let mut delay = RETRY_DELAY;
+
+        let mut attempt = 0;
+        loop {
+            match call(&mut self.device).await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < attempts => {
+                    attempt += 1;
+                    self.state.write().unwrap().log_error(format!("Retrying ({attempt}/{attempts}) after: {e}"));
+
+                    async_io::Timer::after(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Remember how to reverse the edit that just finished.
+    fn set_undo(&self, description: String, command: PlayerCommand) {
+        self.state.write().unwrap().undo = Some(Undo { description, command });
+    }
+
+    /// Restart, stop or jump to a random track according to the repeat mode
+    /// and the stop after track option when the device moves past the end of
+    /// a track or the disc by itself.
+    ///
+    /// NetMD does have a play mode command (`netmd_set_playmode` in libnetmd),
+    /// but the minidisc crate doesn't expose it, so this is done from here.
+    async fn repeat(&mut self, status: &DeviceStatus) -> Result<()> {
+        // Nothing can be told from a status caught between two states, so
+        // wait for the next one rather than taking it as having stopped
+        if status.state.is_none() {
+            return Ok(());
+        }
+
+        let playing = status.state == Some(OS::Playing);
+        let stopped = status.state == Some(OS::Ready);
+
+        if let Some(previous) = self.last_playing {
+            let (mode, stop_after_track, track_count) = {
+                let state = self.state.read().unwrap();
+                let track_count = state.disc_contents.as_ref().map_or(0, |dc| dc.track_count() as usize);
+                (state.repeat_mode, state.stop_after_track, track_count)
+            };
+
+            let track_finished = (playing && status.track == previous + 1) || stopped;
+            let disc_finished = stopped && previous as usize + 1 == track_count;
+
+            if stop_after_track && track_finished {
+                if playing {
+                    self.device.interface_mut().stop().await?;
+                }
+                self.state.write().unwrap().stop_after_track = false;
+                self.last_playing = None;
+                return Ok(());
+            }
+
+            if mode == RepeatMode::Shuffle && track_finished {
+                self.shuffle_played.insert(previous);
+            }
+
+            let restart_from = match mode {
+                RepeatMode::Track if track_finished => Some(previous),
+                RepeatMode::Disc if disc_finished => Some(0),
+                RepeatMode::Shuffle if track_finished => {
+                    let unplayed: Vec<u8> = (0..track_count as u8)
+                        .filter(|t| !self.shuffle_played.contains(t))
+                        .collect();
+
+                    if unplayed.is_empty() {
+                        // Every track has been played once
+                        if playing {
+                            self.device.interface_mut().stop().await?;
+                        }
+                        self.shuffle_played.clear();
+                        self.last_playing = None;
+                        return Ok(());
+                    }
+                    Some(unplayed[random_below(unplayed.len())])
+                }
+                _ => None,
+            };
+
+            if let Some(track) = restart_from {
+                self.device.interface_mut().go_to_track(track as u16).await?;
+                self.device.interface_mut().playback_control(Action::Play).await?;
+                self.last_playing = Some(track);
+                return Ok(());
+            }
+        }
+
+        self.last_playing = playing.then_some(status.track);
+
+        Ok(())
+    }
+
+    /// Jump back to the start of the A-B loop once playback passes its end,
+    /// and forget the loop if playback leaves its track.
+    async fn ab_loop(&mut self, status: &DeviceStatus) -> Result<()> {
+        let Some(ab_loop) = self.state.read().unwrap().ab_loop else {
+            return Ok(());
+        };
+
+        if status.state != Some(OS::Playing) {
+            return Ok(());
+        }
+
+        if status.track != ab_loop.track {
+            self.state.write().unwrap().ab_loop = None;
+        } else if Duration::from(status.time) >= ab_loop.end {
+            self.go_to_time(ab_loop.track, ab_loop.start).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn go_to_time(&mut self, track: u8, time: Duration) -> Result<()> {
+        let secs = time.as_secs();
+        self.device.interface_mut().go_to_time(
+            track as u16,
+            (secs / 3600) as u8,
+            ((secs / 60) % 60) as u8,
+            (secs % 60) as u8,
+            0,
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Carry out a single command from the UI.
+    async fn run_command(&mut self, command: PlayerCommand) -> Result<()> {
+        match command {
+            PlayerCommand::Disconnect => (), // Handled by the command loop
+            PlayerCommand::Playback(action) => {
+                self.retry(async |d| Ok(d.interface_mut().playback_control(action).await?)).await?;
+            },
+            // Not retried, since a change which reached the device before its
+            // reply was lost would skip twice
+            PlayerCommand::SkipTracks(1) => {
+                self.device.interface_mut().track_change(Direction::Next).await?;
+            },
+            PlayerCommand::SkipTracks(-1) => {
+                self.device.interface_mut().track_change(Direction::Previous).await?;
+            },
+            PlayerCommand::SkipTracks(tracks) => {
+                let (current, track_count) = {
+                    let state = self.state.read().unwrap();
+                    (
+                        state.device_state.map_or(0, |s| s.track as isize),
+                        state.disc_contents.as_ref().map_or(0, |dc| dc.track_count() as isize),
+                    )
+                };
+
+                let target = (current + tracks).clamp(0, (track_count - 1).max(0));
+                self.retry(async |d| Ok(d.interface_mut().go_to_track(target as u16).await?)).await?;
+
+                // Show where the skips ended up straight away
+                self.state.write().unwrap().device_state = Some(self.device.device_status().await?);
+            },
+            PlayerCommand::GoToTrack(track) => {
+                // The table may still be showing a disc that has since changed
+                let track_count = self.track_count();
+                if track >= track_count {
+                    log::warn!("Ignoring request to go to track {} of {track_count}", track + 1);
+                    return Ok(());
+                }
+
+                self.retry(async |d| Ok(d.interface_mut().go_to_track(track as u16).await?)).await?;
+                self.retry(async |d| Ok(d.interface_mut().playback_control(Action::Play).await?)).await?;
+            },
+            PlayerCommand::Seek(time) => {
+                let track = self.state.read().unwrap().device_state.map(|s| s.track);
+                if let Some(track) = track {
+                    self.go_to_time(track, time).await?;
+                }
+            }
+            PlayerCommand::SetLoop(ab_loop) => {
+                self.state.write().unwrap().ab_loop = ab_loop;
+            }
+            PlayerCommand::SetRepeatMode(mode) => {
+                self.state.write().unwrap().repeat_mode = mode;
+                self.shuffle_played.clear();
+            }
+            PlayerCommand::SetStopAfterTrack(stop) => {
+                self.state.write().unwrap().stop_after_track = stop;
+            }
+            PlayerCommand::Stop => {
+                self.retry(async |d| Ok(d.interface_mut().stop().await?)).await?;
+            }
+            PlayerCommand::Upload(job) => {
+                let format = job.format;
+                let previous_count = self.track_count();
+                self.state.write().unwrap().current_upload = Some((job.path.clone(), job.title.clone()));
+
+                let chunk_size = self.settings.read().unwrap().chunk_size();
+                let result = match job.read_track(chunk_size) {
+                    Ok(track) => self.upload(track, format).await,
+                    Err(e) => {
+                        self.state.write().unwrap().report_error(e.to_string());
+                        Ok(false)
+                    }
+                };
+
+                self.state.write().unwrap().current_upload = None;
+                let uploaded = result?;
+                self.get_contents().await?;
+
+                if uploaded {
+                    self.play_after_upload(previous_count).await?;
+                }
+            }
+            PlayerCommand::UploadQueue(jobs) => {
+                let previous_count = self.track_count();
+                let total = jobs.len();
+                let mut errors = Vec::new();
+                let mut cancelled = false;
+                for (i, job) in jobs.into_iter().enumerate() {
+                    self.state.write().unwrap().queue_position = Some((i + 1, total));
+
+                    let path = job.path.clone();
+                    let format = job.format;
+                    self.state.write().unwrap().current_upload = Some((path.clone(), job.title.clone()));
+
+                    let chunk_size = self.settings.read().unwrap().chunk_size();
+                    let track = match job.read_track(chunk_size) {
+                        Ok(track) => track,
+                        Err(e) => {
+                            errors.push(e.to_string());
+                            continue;
+                        }
+                    };
+
+                    let result = self.upload(track, format).await;
+                    self.get_contents().await?;
+
+                    match result {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            cancelled = true;
+                            break;
+                        }
+                        Err(e) => errors.push(format!("{}: {e}", path.display())),
+                    }
+                }
+
+                {
+                    let mut state = self.state.write().unwrap();
+                    state.queue_position = None;
+                    state.current_upload = None;
+                    state.upload_errors.extend(errors);
+                }
+
+                if !cancelled {
+                    self.play_after_upload(previous_count).await?;
+                }
+            }
+            PlayerCommand::DownloadTrack { index, dest } => {
+                self.download_track(index, dest).await?;
+            }
+            PlayerCommand::RenameTrack { index, title } => {
+                let previous = self.state.read().unwrap().disc_contents.as_ref()
+                    .and_then(|dc| dc.tracks().get(index).map(track_titles));
+
+                self.rename_track(index, &title).await?;
+                self.get_contents().await?;
+
+                // Both titles are put back as they were, since either one
+                // alone can't be rebuilt from the other
+                if let Some(previous) = previous {
+                    self.set_undo(
+                        format!("renaming track {}", index + 1),
+                        PlayerCommand::RenameTracks(vec![(index, previous)]),
+                    );
+                }
+            }
+            PlayerCommand::RenameTracks(renames) => {
+                let previous: Vec<(usize, TrackTitles)> = self.state.read().unwrap().disc_contents.as_ref()
+                    .map(|dc| {
+                        let tracks = dc.tracks();
+                        renames.iter()
+                            .filter_map(|(index, _)| tracks.get(*index).map(|t| (*index, track_titles(t))))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                for (index, titles) in &renames {
+                    // Tracks no longer on the disc are skipped
+                    if let Some((_, current)) = previous.iter().find(|(i, _)| i == index) {
+                        self.set_track_titles(*index, titles, current).await?;
+                    }
+                }
+                self.get_contents().await?;
+
+                let description = match renames.as_slice() {
+                    [(index, _)] => format!("renaming track {}", index + 1),
+                    _ => format!("renaming {} tracks", renames.len()),
+                };
+                self.set_undo(description, PlayerCommand::RenameTracks(previous));
+            }
+            PlayerCommand::SetDiscTitle(title) => {
+                let previous = self.state.read().unwrap().disc_contents.as_ref().map(|dc| dc.title().clone());
+
+                let title = sanitize_title(&title);
+                let half_width = half_width_title(&title);
+                let full_width = (half_width != title).then_some(title.as_str());
+                self.device.rename_disc(&half_width, full_width).await?;
+                self.get_contents().await?;
+
+                if let Some(previous) = previous {
+                    self.set_undo("renaming the disc".to_string(), PlayerCommand::SetDiscTitle(previous));
+                }
+            }
+            PlayerCommand::MoveTrack { from, to } => {
+                if from != to {
+                    let groups = self.state.read().unwrap().groups.clone();
+                    let track_count = self.track_count();
+
+                    self.device.interface_mut().move_track(from as u16, to as u16).await?;
+                    // Group ranges are by position, so they have to follow the tracks
+                    self.edit_groups(|g| *g = move_track(&groups, track_count, from, to)).await?;
+
+                    // Update the playing track straight away so the highlight follows it
+                    self.state.write().unwrap().device_state = Some(self.device.device_status().await?);
+
+                    self.set_undo(
+                        format!("moving track {}", from + 1),
+                        PlayerCommand::MoveTrack { from: to, to: from },
+                    );
+                }
+            }
+            PlayerCommand::CreateGroup { first, last, title } => {
+                let title = sanitize_title(&title);
+                self.edit_groups(|groups| {
+                    groups.retain(|g| !g.overlaps(first, last));
+                    groups.push(TrackGroup { title, first, last });
+                }).await?;
+
+                let group = self.state.read().unwrap().groups.iter().position(|g| g.first == first);
+                if let Some(group) = group {
+                    self.set_undo("creating a group".to_string(), PlayerCommand::Ungroup(group));
+                }
+            }
+            PlayerCommand::RenameGroup { group, title } => {
+                let previous = self.state.read().unwrap().groups.get(group).map(|g| g.title.clone());
+
+                let title = sanitize_title(&title);
+                self.edit_groups(|groups| {
+                    if let Some(g) = groups.get_mut(group) {
+                        g.title = title;
+                    }
+                }).await?;
+
+                if let Some(previous) = previous {
+                    self.set_undo("renaming a group".to_string(), PlayerCommand::RenameGroup { group, title: previous });
+                }
+            }
+            PlayerCommand::MoveTrackToGroup { track, group } => {
+                // The undo's track numbers won't line up with the moved track
+                self.state.write().unwrap().undo = None;
+
+                let (groups, track_count) = {
+                    let state = self.state.read().unwrap();
+                    let track_count = state.disc_contents.as_ref().map_or(0, |dc| dc.track_count() as usize);
+                    (state.groups.clone(), track_count)
+                };
+                if track >= track_count {
+                    return Ok(());
+                }
+
+                let (to, moved) = move_to_group(&groups, track_count, track, group, NEW_GROUP_TITLE);
+                if to != track {
+                    self.device.interface_mut().move_track(track as u16, to as u16).await?;
+                }
+                self.edit_groups(|groups| *groups = moved).await?;
+            }
+            PlayerCommand::Ungroup(group) => {
+                let previous = self.state.read().unwrap().groups.get(group).cloned();
+
+                self.edit_groups(|groups| {
+                    if group < groups.len() {
+                        groups.remove(group);
+                    }
+                }).await?;
+
+                if let Some(TrackGroup { title, first, last }) = previous {
+                    self.set_undo("ungrouping".to_string(), PlayerCommand::CreateGroup { first, last, title });
+                }
+            }
+            PlayerCommand::EraseDisc => {
+                self.state.write().unwrap().undo = None;
+                self.state.write().unwrap().reading = true;
+                self.device.interface_mut().stop().await?;
+                self.device.interface_mut().erase_disc().await?;
+                self.get_contents().await?;
+            }
+            PlayerCommand::Eject => {
+                self.device.interface_mut().eject_disc().await?;
+                self.state.write().unwrap().disc_contents = None;
+                self.state.write().unwrap().toc_error = None;
+                self.state.write().unwrap().undo = None;
+            }
+            PlayerCommand::CancelUpload => (), // Only meaningful while a transfer is running
+            PlayerCommand::Refresh => {
+                let status = self.device.device_status().await?;
+                self.state.write().unwrap().device_state = Some(status);
+
+                if status.disc_present {
+                    self.get_contents().await?;
+                } else {
+                    self.state.write().unwrap().disc_contents = None;
+                    self.state.write().unwrap().toc_error = None;
+                }
+            }
+            PlayerCommand::Delete(mut tracks) => {
+                // Track numbers in the undo would point at the wrong tracks afterwards
+                self.state.write().unwrap().undo = None;
+                self.state.write().unwrap().reading = true;
+                self.device.interface_mut().stop().await?;
+                let groups = self.state.read().unwrap().groups.clone();
+                let track_count = self.track_count();
+
+                // Erase from the end so earlier erases don't shift later indices
+                tracks.sort_unstable_by(|a, b| b.cmp(a));
+                tracks.dedup();
+                let mut erased = Vec::new();
+                let mut failure = None;
+                for track in tracks {
+                    if let Err(e) = self.device.interface_mut().erase_track(track as u16).await {
+                        failure = Some(format!("Could not delete track {}: {e}", track + 1));
+                        break;
+                    }
+                    erased.push(track);
+                }
+                self.state.write().unwrap().reading = false;
+
+                // Group ranges are by position, so they have to close up over
+                // whichever tracks were erased
+                let regrouped = self.edit_groups(|g| *g = remove_tracks(&groups, track_count, &erased)).await;
+                if let Err(e) = regrouped {
+                    failure.get_or_insert(format!("Could not update the groups: {e}"));
+
+                    // Some tracks may be gone anyway, so read the disc again
+                    // to show what's really left
+                    self.get_contents().await?;
+                }
+
+                if let Some(failure) = failure {
+                    self.state.write().unwrap().report_error(failure);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn command_loop(&mut self) -> Result<()> {
+        // Not every model has a motorized eject, so only offer it if it works
+        let can_eject = self.device.interface_mut().can_eject_disc().await.unwrap_or(false);
+        self.state.write().unwrap().can_eject = can_eject;
+
+        self.state.write().unwrap().device_state = Some(self.device.device_status().await?);
+        self.state.write().unwrap().last_status = Some(Instant::now());
+        self.read_new_disc().await?;
+
+        let mut state_timer = Instant::now();
+        let mut disc_check_timer = Instant::now();
+        loop {
+            let settings = *self.settings.read().unwrap();
+
+            // Handle commands as soon as they arrive, but wake up in time for
+            // the next status poll
+            let until_poll = settings.poll_interval.saturating_sub(state_timer.elapsed());
+            let recv = &self.recv;
+            let command = match self.deferred.pop_front() {
+                Some(command) => Some(Ok(command)),
+                None => future::or(
+                    async { Some(recv.recv().await) },
+                    async {
+                        async_io::Timer::after(until_poll).await;
+                        None
                     },
-                    PlayerCommand::Stop => {
-                        self.device.interface_mut().stop().await?;
-                    }
-                    PlayerCommand::Upload(path) => {
-                        let track_contents: Vec<u8> = std::fs::read(path).unwrap().to_vec();
-                        let track = MDTrack {
-                            chunk_size: 0x400,
-                            title: String::from("TestTrack"),
-                            full_width_title: None,
-                            format: minidisc::netmd::interface::WireFormat::LP4,
-                            data: track_contents,
-                        };
-                        self.device.interface_mut().stop().await?;
+                ).await,
+            };
+
+            let command = match command {
+                Some(Ok(command)) => Some(command),
+                // The UI has gone away, so there's nothing left to control the device
+                Some(Err(_)) => break,
+                None => None,
+            };
+
+            if let Some(r) = command {
+                // Any change in playback from here on is the user's doing
+                self.last_playing = None;
+
+                if let PlayerCommand::Disconnect = r {
+                    break;
+                }
+
+                if r.changes_disc() {
+                    self.forget_cached_disc();
+                }
+
+                let failure = r.failure_message();
+                let description = format!("{r:?}");
+                self.state.write().unwrap().busy_tracks = r.affected_tracks();
+                let result = self.run_command(r).await;
+                self.state.write().unwrap().busy_tracks.clear();
+                self.state.write().unwrap().log_command(description, result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
 
-                        let player_state_thread = Arc::clone(&self.state);
-                        self.device.download(track, |out_of: usize, done: usize| {
-                            player_state_thread.write().unwrap().progress = Some(done as f32/out_of as f32)
-                        }).await?;
-                        self.state.write().unwrap().progress = None;
-                        self.get_contents().await?;
+                if let Err(e) = result {
+                    {
+                        let mut state = self.state.write().unwrap();
+                        state.reading = false;
+                        state.preparing_transfer = false;
+                        state.progress = None;
+                        state.transfer_speed = None;
+                        state.current_upload = None;
+                        state.queue_position = None;
                     }
-                    PlayerCommand::Delete(track) => {
-                        self.state.write().unwrap().reading = true;
-                        self.device.interface_mut().stop().await?;
-                        self.device.interface_mut().erase_track(track as u16).await?;
-                        self.get_contents().await?;
+
+                    // Only give up on the device if it has stopped answering
+                    if self.device.device_status().await.is_err() {
+                        return Err(e);
                     }
+                    self.state.write().unwrap().report_error(format!("{failure}: {e}"));
                 }
             }
 
             // Check for an updated device state
-            if state_timer.elapsed() >= Duration::from_millis(500) {
-                let state = self.device.device_status().await?;
+            if state_timer.elapsed() >= settings.poll_interval {
+                let state = self.retry(async |d| Ok(d.device_status().await?)).await?;
 
                 self.state.write().unwrap().device_state = Some(state);
+                self.state.write().unwrap().last_status = Some(Instant::now());
+                self.repeat(&state).await?;
+                self.ab_loop(&state).await?;
 
-                let contents_present = self.state.read().unwrap().disc_contents.is_some();
+                let (contents_present, unreadable) = {
+                    let state = self.state.read().unwrap();
+                    (state.disc_contents.is_some(), state.toc_error.is_some())
+                };
 
                 if contents_present && !state.disc_present {
                     self.state.write().unwrap().disc_contents = None;
-                } else if !contents_present && state.disc_present {
-                    self.get_contents().await?;
+                    self.state.write().unwrap().undo = None;
+                } else if unreadable && !state.disc_present {
+                    self.state.write().unwrap().toc_error = None;
+                } else if !contents_present
+                    && !unreadable
+                    && state.disc_present
+                    && state.state != Some(OS::ReadingTOC)
+                {
+                    // An unreadable disc is only tried again when the user asks
+                    self.read_new_disc().await?;
+                } else if contents_present
+                    && state.state != Some(OS::ReadingTOC)
+                    && disc_check_timer.elapsed() >= DISC_CHECK_INTERVAL
+                {
+                    // A quick swap may never show up as a missing disc
+                    disc_check_timer = Instant::now();
+                    if self.disc_changed().await {
+                        log::info!("The disc was swapped, reading it again");
+                        self.state.write().unwrap().undo = None;
+                        self.read_new_disc().await?;
+                    }
                 }
 
                 state_timer = Instant::now();
             }
-
-            sleep(Duration::from_millis(50));
         }
 
         Ok(())
     }
 }
+
+/// Run a transfer until it completes, or return `None` if the user cancels it
+/// first.
+async fn cancellable<T>(
+    recv: &async_channel::Receiver<PlayerCommand>,
+    cancel: &AtomicBool,
+    deferred: &mut VecDeque<PlayerCommand>,
+    transfer: impl Future<Output = T>,
+) -> Option<T> {
+    future::or(
+        async { Some(transfer.await) },
+        async {
+            wait_for_cancel(recv, cancel, deferred).await;
+            None
+        },
+    ).await
+}
+
+/// Wait until the user asks for the running upload to be cancelled, or
+/// disconnects, or the UI goes away.
+///
+/// The device can't do anything else during a transfer, so any other
+/// commands are put aside in `deferred` to be run afterwards.
+async fn wait_for_cancel(
+    recv: &async_channel::Receiver<PlayerCommand>,
+    cancel: &AtomicBool,
+    deferred: &mut VecDeque<PlayerCommand>,
+) {
+    loop {
+        match recv.recv().await {
+            Ok(PlayerCommand::CancelUpload) | Err(_) => {
+                cancel.store(true, Ordering::Relaxed);
+                return;
+            }
+            Ok(PlayerCommand::Disconnect) => {
+                cancel.store(true, Ordering::Relaxed);
+                // Still disconnect once the transfer has stopped
+                deferred.push_back(PlayerCommand::Disconnect);
+                return;
+            }
+            Ok(command) => deferred.push_back(command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_fraction_with_no_total() {
+        assert_eq!(transfer_fraction(0, 0), 0.0);
+        assert_eq!(transfer_fraction(10, 0), 0.0);
+    }
+
+    #[test]
+    fn transfer_fraction_stays_in_range() {
+        assert_eq!(transfer_fraction(0, 100), 0.0);
+        assert_eq!(transfer_fraction(50, 100), 0.5);
+        assert_eq!(transfer_fraction(100, 100), 1.0);
+        assert_eq!(transfer_fraction(120, 100), 1.0);
+    }
+
+    #[test]
+    fn csv_field_quotes_line_breaks() {
+        assert_eq!(csv_field("Plain"), "Plain");
+        assert_eq!(csv_field("One, Two"), "\"One, Two\"");
+        assert_eq!(csv_field("Say \"Hi\""), "\"Say \"\"Hi\"\"\"");
+        assert_eq!(csv_field("Line\nBreak"), "\"Line\nBreak\"");
+        assert_eq!(csv_field("Line\rBreak"), "\"Line\rBreak\"");
+    }
+}
@@ -0,0 +1,194 @@
+//! Renaming many tracks at once.
+//!
+//! A track can have both a half-width and a full-width title, which often
+//! aren't the same text in two scripts, so each is renamed on its own.
+
+use regex::{NoExpand, Regex, RegexBuilder};
+
+/// A track's half-width and full-width titles
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrackTitles {
+    pub title: String,
+    pub full_width_title: String,
+}
+
+impl TrackTitles {
+    /// The title to show for the track, preferring the full-width one
+    pub fn display(&self) -> &str {
+        if self.full_width_title.is_empty() {
+            &self.title
+        } else {
+            &self.full_width_title
+        }
+    }
+
+    /// Change each title which isn't empty.
+    fn map(&self, mut rename: impl FnMut(&str) -> String) -> Self {
+        let mut rename_field = |field: &str| if field.is_empty() { String::new() } else { rename(field) };
+        Self {
+            title: rename_field(&self.title),
+            full_width_title: rename_field(&self.full_width_title),
+        }
+    }
+}
+
+/// Text to look for in every track title, and what to put in its place
+#[derive(Debug, Default, Clone)]
+pub struct FindReplace {
+    pub find: String,
+    pub replace: String,
+    /// Treat `find` as a regular expression, so `replace` can refer to its
+    /// groups with `$1`
+    pub regex: bool,
+    pub match_case: bool,
+}
+
+impl FindReplace {
+    /// The index and new titles of each track whose titles would change, or
+    /// why the pattern is invalid.
+    pub fn renames(&self, titles: &[TrackTitles]) -> Result<Vec<(usize, TrackTitles)>, String> {
+        if self.find.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let pattern = if self.regex {
+            self.find.clone()
+        } else {
+            regex::escape(&self.find)
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.match_case)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(titles.iter()
+            .enumerate()
+            .filter_map(|(i, current)| {
+                let renamed = current.map(|title| if self.regex {
+                    re.replace_all(title, self.replace.as_str()).into_owned()
+                } else {
+                    re.replace_all(title, NoExpand(&self.replace)).into_owned()
+                });
+                (renamed != *current).then_some((i, renamed))
+            })
+            .collect())
+    }
+}
+
+/// Titles for tracks in order, built from a template with `{n}` for the
+/// track's number and `{title}` for its current title
+#[derive(Debug, Clone)]
+pub struct Numbering {
+    pub template: String,
+    /// Used instead of `template` for tracks without a title
+    pub untitled_template: String,
+    /// The number given to the first track
+    pub start: usize,
+    /// Pad numbers with zeros to at least this many digits
+    pub digits: usize,
+    /// Take off any number and separator a title already starts with, so
+    /// tracks can be numbered again after they're reordered
+    pub replace_numbers: bool,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Self {
+            template: "{n} - {title}".to_string(),
+            untitled_template: "Track {n}".to_string(),
+            start: 1,
+            digits: 2,
+            replace_numbers: true,
+        }
+    }
+}
+
+impl Numbering {
+    /// The new titles of each track whose titles would change, numbering the
+    /// given tracks in order.
+    pub fn renames(&self, tracks: &[(usize, TrackTitles)]) -> Vec<(usize, TrackTitles)> {
+        // Numbers like "1. ", "01 - " or "1)", but not ones which are part of
+        // the title like "99 Luftballons" or "1.5 Hours"
+        let existing_number = Regex::new(r"^\d+\s*[-.):]\s*(?<rest>\D|$)").unwrap();
+
+        tracks.iter()
+            .enumerate()
+            .filter_map(|(i, (index, current))| {
+                let number = format!("{:0width$}", self.start + i, width = self.digits);
+                let strip = |title: &str| if self.replace_numbers {
+                    existing_number.replace(title, "$rest").trim().to_string()
+                } else {
+                    title.trim().to_string()
+                };
+
+                let untitled = strip(&current.title).is_empty() && strip(&current.full_width_title).is_empty();
+                let renamed = if untitled {
+                    TrackTitles {
+                        title: self.untitled_template.replace("{n}", &number),
+                        full_width_title: String::new(),
+                    }
+                } else {
+                    current.map(|title| {
+                        let title = strip(title);
+                        if title.is_empty() {
+                            String::new()
+                        } else {
+                            self.template.replace("{n}", &number).replace("{title}", &title)
+                        }
+                    })
+                };
+
+                (renamed != *current).then_some((*index, renamed))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(title: &str, full_width_title: &str) -> TrackTitles {
+        TrackTitles { title: title.to_string(), full_width_title: full_width_title.to_string() }
+    }
+
+    #[test]
+    fn find_replace_keeps_each_title() {
+        let find_replace = FindReplace { find: "o".to_string(), replace: "0".to_string(), ..Default::default() };
+        let renames = find_replace.renames(&[titles("Hello", "こんにちは"), titles("Bye", "")]).unwrap();
+
+        assert_eq!(renames, vec![(0, titles("Hell0", "こんにちは"))]);
+    }
+
+    #[test]
+    fn numbering_keeps_each_title() {
+        let renames = Numbering::default().renames(&[
+            (0, titles("Hello", "こんにちは")),
+            (1, titles("02 - Bye", "")),
+            (2, titles("", "")),
+        ]);
+
+        // The second track is already numbered, so it's left alone
+        assert_eq!(renames, vec![
+            (0, titles("01 - Hello", "01 - こんにちは")),
+            (2, titles("Track 03", "")),
+        ]);
+    }
+
+    #[test]
+    fn numbering_keeps_numbers_in_titles() {
+        let renames = Numbering::default().renames(&[
+            (0, titles("99 Luftballons", "")),
+            (1, titles("1999 Remaster", "")),
+            (2, titles("1.5 Hours", "")),
+            (3, titles("7.Seven", "")),
+        ]);
+
+        assert_eq!(renames, vec![
+            (0, titles("01 - 99 Luftballons", "")),
+            (1, titles("02 - 1999 Remaster", "")),
+            (2, titles("03 - 1.5 Hours", "")),
+            (3, titles("04 - Seven", "")),
+        ]);
+    }
+}
@@ -0,0 +1,147 @@
+//! User preferences, which are saved between sessions.
+
+use std::{path::PathBuf, time::Duration};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::{UploadFormat, DEFAULT_PIXELS_PER_POINT};
+
+/// Storage key for [`Settings`], the window geometry is saved by eframe itself
+pub const SETTINGS_KEY: &str = "settings";
+
+/// Storage key for the upload queue, if the app was closed before starting it
+pub const UPLOAD_QUEUE_KEY: &str = "upload_queue";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The format new tracks are recorded in
+    pub upload_format: UploadFormat,
+    /// Delete tracks without asking first
+    pub skip_delete_confirmation: bool,
+    /// The directory files to upload were last picked from
+    pub last_upload_dir: Option<PathBuf>,
+    /// Stop the computer from sleeping while uploading
+    pub keep_awake: bool,
+    /// Ask before closing the window while uploading
+    pub confirm_quit: bool,
+
+    /// The UI scale as of the last frame
+    pub pixels_per_point: f32,
+    pub theme: egui::ThemePreference,
+    pub duration_format: DurationFormat,
+    pub table_density: TableDensity,
+
+    pub device: DeviceSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            upload_format: UploadFormat::default(),
+            skip_delete_confirmation: false,
+            last_upload_dir: None,
+            keep_awake: true,
+            confirm_quit: true,
+            pixels_per_point: DEFAULT_PIXELS_PER_POINT,
+            theme: egui::ThemePreference::default(),
+            duration_format: DurationFormat::default(),
+            table_density: TableDensity::default(),
+            device: DeviceSettings::default(),
+        }
+    }
+}
+
+/// How track and disc times are written out
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DurationFormat {
+    /// `mm:ss`, with hours only when needed
+    #[default]
+    Compact,
+    /// Always `hh:mm:ss`, so every time is the same width
+    Full,
+}
+
+/// How much room each row of the track listing gets
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TableDensity {
+    Compact,
+    #[default]
+    Normal,
+    Comfortable,
+}
+
+impl TableDensity {
+    pub const ALL: [Self; 3] = [Self::Compact, Self::Normal, Self::Comfortable];
+
+    /// The height of a row holding text of the given size, where
+    /// `interact_height` is the height of a button.
+    pub fn row_height(self, text_height: f32, interact_height: f32) -> f32 {
+        match self {
+            Self::Compact => text_height + 2.0,
+            Self::Normal => text_height.max(interact_height),
+            Self::Comfortable => text_height.max(interact_height) + 10.0,
+        }
+    }
+}
+
+impl std::fmt::Display for TableDensity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compact => write!(f, "Compact"),
+            Self::Normal => write!(f, "Normal"),
+            Self::Comfortable => write!(f, "Comfortable"),
+        }
+    }
+}
+
+/// Settings which control how the worker thread talks to the device
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Try to connect again after the device is lost
+    pub auto_reconnect: bool,
+    pub reconnect_interval: Duration,
+    pub reconnect_attempts: u32,
+    /// How often to ask the device for its status
+    pub poll_interval: Duration,
+    /// How many times to retry a failed status poll or playback command
+    pub retry_attempts: u32,
+    /// How many bytes of audio are sent in each USB transfer
+    pub upload_chunk_size: usize,
+    /// Start playing a track as soon as it's been uploaded
+    pub play_after_upload: bool,
+}
+
+/// The upload chunk size every device is known to work with
+pub const DEFAULT_CHUNK_SIZE: usize = 0x400;
+
+/// The upload chunk sizes which can be chosen
+pub const CHUNK_SIZES: [usize; 5] = [0x100, 0x200, 0x400, 0x800, 0x1000];
+
+impl DeviceSettings {
+    /// The chunk size to upload with, falling back to the default if the
+    /// saved one isn't a known size.
+    pub fn chunk_size(&self) -> usize {
+        if CHUNK_SIZES.contains(&self.upload_chunk_size) {
+            self.upload_chunk_size
+        } else {
+            DEFAULT_CHUNK_SIZE
+        }
+    }
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            auto_reconnect: false,
+            reconnect_interval: Duration::from_secs(2),
+            reconnect_attempts: 10,
+            poll_interval: Duration::from_millis(500),
+            retry_attempts: 2,
+            upload_chunk_size: DEFAULT_CHUNK_SIZE,
+            play_after_upload: false,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Fitting titles into the character sets a device can store.
+//!
+//! Half-width titles are plain ASCII, and full-width titles are Shift-JIS,
+//! which covers Japanese text but very little else.
+
+/// The number of title cells in a disc's table of contents
+const TOC_TITLE_CELLS: usize = 255;
+
+/// The number of characters stored in each table of contents title cell
+const TOC_CELL_SIZE: usize = 7;
+
+/// Characters with a close enough ASCII equivalent
+const TRANSLITERATIONS: &[(&str, &str)] = &[
+    ("‘’‚′", "'"),
+    ("“”„″", "\""),
+    ("‐‑‒–—―", "-"),
+    ("…", "..."),
+    ("×", "x"),
+    ("àáâãäåā", "a"),
+    ("ÀÁÂÃÄÅĀ", "A"),
+    ("æ", "ae"),
+    ("Æ", "AE"),
+    ("çćč", "c"),
+    ("ÇĆČ", "C"),
+    ("èéêëē", "e"),
+    ("ÈÉÊËĒ", "E"),
+    ("ìíîïī", "i"),
+    ("ÌÍÎÏĪ", "I"),
+    ("ñń", "n"),
+    ("ÑŃ", "N"),
+    ("òóôõöøō", "o"),
+    ("ÒÓÔÕÖØŌ", "O"),
+    ("œ", "oe"),
+    ("Œ", "OE"),
+    ("ß", "ss"),
+    ("šś", "s"),
+    ("ŠŚ", "S"),
+    ("ùúûüū", "u"),
+    ("ÙÚÛÜŪ", "U"),
+    ("ýÿ", "y"),
+    ("Ý", "Y"),
+    ("žźż", "z"),
+    ("ŽŹŻ", "Z"),
+];
+
+/// Whether a character can be stored as it is, in either a half-width or a
+/// full-width title.
+fn is_storable(c: char) -> bool {
+    (c.is_ascii() && !c.is_ascii_control())
+        || matches!(c,
+            '\u{3000}'..='\u{303F}' // CJK punctuation
+            | '\u{3040}'..='\u{30FF}' // Hiragana and katakana
+            | '\u{4E00}'..='\u{9FFF}' // Kanji
+            | '\u{FF01}'..='\u{FF9F}' // Full-width ASCII and half-width katakana
+        )
+}
+
+/// Replace or remove any characters a device can't store, keeping Japanese
+/// text for the full-width title.
+pub fn sanitize_title(title: &str) -> String {
+    let mut sanitized = String::with_capacity(title.len());
+
+    for c in title.chars() {
+        if is_storable(c) {
+            sanitized.push(c);
+        } else if let Some((_, replacement)) = TRANSLITERATIONS.iter().find(|(from, _)| from.contains(c)) {
+            sanitized.push_str(replacement);
+        }
+    }
+
+    sanitized
+}
+
+/// Describe how a title will be changed when it's saved, if at all.
+pub fn title_change_hint(title: &str) -> Option<String> {
+    let sanitized = sanitize_title(title);
+    (sanitized != title).then(|| {
+        format!("Some characters can't be stored on the disc, so this will be saved as \"{sanitized}\"")
+    })
+}
+
+/// Strip a title down to the characters which can be stored in a half-width
+/// title.
+pub fn half_width_title(title: &str) -> String {
+    title.chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control())
+        .collect()
+}
+
+/// Estimate whether half-width titles will all fit in a disc's table of
+/// contents, where each one takes up a whole number of cells.
+pub fn fit_in_toc(titles: &[&str]) -> bool {
+    let cells: usize = titles.iter()
+        .map(|t| t.len().div_ceil(TOC_CELL_SIZE))
+        .sum();
+
+    cells <= TOC_TITLE_CELLS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_title_transliterates() {
+        assert_eq!(sanitize_title("Café “Déjà Vu” – Œuvre…"), "Cafe \"Deja Vu\" - OEuvre...");
+        assert_eq!(title_change_hint("Plain Title"), None);
+        assert!(title_change_hint("Café").is_some_and(|h| h.contains("\"Cafe\"")));
+    }
+
+    #[test]
+    fn sanitize_title_keeps_japanese_and_drops_the_rest() {
+        assert_eq!(sanitize_title("こんにちは 世界"), "こんにちは 世界");
+        assert_eq!(sanitize_title("Tab\there 🎵 Ω"), "Tabhere  ");
+    }
+
+    #[test]
+    fn half_width_title_keeps_only_ascii() {
+        assert_eq!(half_width_title("Hello こんにちは"), "Hello ");
+        assert_eq!(half_width_title("ＡＢＣ"), "");
+        assert_eq!(half_width_title("Line\nBreak"), "LineBreak");
+    }
+
+    #[test]
+    fn fit_in_toc_counts_whole_cells() {
+        // Every title takes at least one cell, however short
+        assert!(fit_in_toc(&["a"; 255]));
+        assert!(!fit_in_toc(&["a"; 256]));
+
+        // Seven characters fit in a cell, the eighth starts another
+        let seven = "a".repeat(7);
+        let eight = "a".repeat(8);
+        assert!(fit_in_toc(&[seven.as_str(); 255]));
+        assert!(!fit_in_toc(&[eight.as_str(); 128]));
+        assert!(fit_in_toc(&[]));
+    }
+}